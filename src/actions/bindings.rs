@@ -0,0 +1,309 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::mem::discriminant;
+
+use crate::actions::game_control::GameControl;
+use crate::actions::gamepad::{GamepadConfig, StickResponse};
+use crate::config;
+
+const KEY_BINDINGS_FILE: &str = "keybindings.ron";
+
+/// A single key, mouse button or gamepad button that can trigger a [`GameControl`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Matches the button on any connected gamepad
+    Gamepad(GamepadButtonType),
+}
+
+/// Maps every [`GameControl`] to the inputs that trigger it
+/// Loaded from and saved to `keybindings.ron` in the `config` folder next to the executable.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    pub bindings: BTreeMap<GameControl, Vec<InputBinding>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::colemak()
+    }
+}
+
+impl KeyBindings {
+    pub fn qwerty() -> Self {
+        KeyBindings::with_layout([
+            (GameControl::Up, KeyCode::W),
+            (GameControl::Down, KeyCode::S),
+            (GameControl::Left, KeyCode::A),
+            (GameControl::Right, KeyCode::D),
+            (GameControl::Interact, KeyCode::E),
+        ])
+    }
+
+    /// The letter keys sit at the same physical places as on [`KeyBindings::qwerty`]
+    pub fn colemak() -> Self {
+        KeyBindings::with_layout([
+            (GameControl::Up, KeyCode::W),
+            (GameControl::Down, KeyCode::R),
+            (GameControl::Left, KeyCode::A),
+            (GameControl::Right, KeyCode::S),
+            (GameControl::Interact, KeyCode::F),
+        ])
+    }
+
+    // bindings shared by every layout, extended with the layout specific letter keys
+    fn with_layout(letters: [(GameControl, KeyCode); 5]) -> Self {
+        use GamepadButtonType::*;
+        let mut bindings: BTreeMap<GameControl, Vec<InputBinding>> = BTreeMap::from([
+            (
+                GameControl::Up,
+                vec![
+                    InputBinding::Key(KeyCode::Up),
+                    InputBinding::Gamepad(DPadUp),
+                ],
+            ),
+            (
+                GameControl::Down,
+                vec![
+                    InputBinding::Key(KeyCode::Down),
+                    InputBinding::Gamepad(DPadDown),
+                ],
+            ),
+            (
+                GameControl::Left,
+                vec![
+                    InputBinding::Key(KeyCode::Left),
+                    InputBinding::Gamepad(DPadLeft),
+                ],
+            ),
+            (
+                GameControl::Right,
+                vec![
+                    InputBinding::Key(KeyCode::Right),
+                    InputBinding::Gamepad(DPadRight),
+                ],
+            ),
+            (
+                GameControl::Sprint,
+                vec![
+                    InputBinding::Key(KeyCode::ShiftLeft),
+                    InputBinding::Gamepad(LeftThumb),
+                ],
+            ),
+            (
+                GameControl::Walk,
+                vec![InputBinding::Key(KeyCode::ControlLeft)],
+            ),
+            (
+                GameControl::Jump,
+                vec![
+                    InputBinding::Key(KeyCode::Space),
+                    InputBinding::Gamepad(South),
+                ],
+            ),
+            (
+                GameControl::Interact,
+                vec![
+                    InputBinding::Mouse(MouseButton::Left),
+                    InputBinding::Gamepad(West),
+                ],
+            ),
+            (
+                GameControl::Pause,
+                vec![
+                    InputBinding::Key(KeyCode::Escape),
+                    InputBinding::Gamepad(Start),
+                ],
+            ),
+            (
+                GameControl::CycleSkybox,
+                vec![InputBinding::Key(KeyCode::C), InputBinding::Gamepad(North)],
+            ),
+            (
+                GameControl::DebugRender,
+                vec![InputBinding::Key(KeyCode::F1)],
+            ),
+        ]);
+        for (control, key) in letters {
+            bindings
+                .entry(control)
+                .or_default()
+                .insert(0, InputBinding::Key(key));
+        }
+        KeyBindings { bindings }
+    }
+
+    /// Loads the saved bindings, falling back to the defaults
+    pub fn load() -> Self {
+        let mut bindings: KeyBindings = config::load(KEY_BINDINGS_FILE).unwrap_or_default();
+        // controls added after the file was written keep their default bindings
+        for (control, defaults) in KeyBindings::default().bindings {
+            bindings.bindings.entry(control).or_insert(defaults);
+        }
+        bindings.warn_conflicts();
+        bindings
+    }
+
+    /// Saves the bindings, warning about inputs shared by several controls
+    pub fn save(&self) {
+        self.warn_conflicts();
+        config::save(KEY_BINDINGS_FILE, self);
+    }
+
+    pub fn get(&self, control: GameControl) -> &[InputBinding] {
+        self.bindings
+            .get(&control)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Replaces the inputs of the control from the same kind of device as the given one
+    /// A new key binding keeps the mouse and gamepad bindings of the control, and so on.
+    pub fn bind(&mut self, control: GameControl, binding: InputBinding) {
+        let same_device = |bound: &InputBinding| discriminant(bound) == discriminant(&binding);
+        let bindings = self.bindings.entry(control).or_default();
+        // the new binding takes the place of the first one it replaces
+        let index = bindings
+            .iter()
+            .position(same_device)
+            .unwrap_or(bindings.len());
+        bindings.retain(|bound| !same_device(bound));
+        bindings.insert(index, binding);
+    }
+
+    /// Every input that triggers more than one control, with the controls sharing it
+    pub fn conflicts(&self) -> Vec<(InputBinding, Vec<GameControl>)> {
+        let mut users: HashMap<InputBinding, Vec<GameControl>> = HashMap::default();
+        for (control, bindings) in &self.bindings {
+            for binding in bindings {
+                let controls = users.entry(*binding).or_default();
+                if !controls.contains(control) {
+                    controls.push(*control);
+                }
+            }
+        }
+        users
+            .into_iter()
+            .filter(|(_, controls)| controls.len() > 1)
+            .collect()
+    }
+
+    fn warn_conflicts(&self) {
+        for (binding, controls) in self.conflicts() {
+            warn!("{binding:?} is bound to more than one control: {controls:?}");
+        }
+    }
+}
+
+/// Everything needed to check whether a [`GameControl`] is active
+#[derive(SystemParam)]
+pub struct ControlInput<'w> {
+    bindings: Res<'w, KeyBindings>,
+    keyboard: Res<'w, Input<KeyCode>>,
+    mouse: Res<'w, Input<MouseButton>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepad_button_axes: Res<'w, Axis<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    gamepad_config: Res<'w, GamepadConfig>,
+}
+
+impl ControlInput<'_> {
+    pub fn pressed(&self, control: GameControl) -> bool {
+        self.bindings
+            .get(control)
+            .iter()
+            .any(|binding| self.binding_pressed(*binding))
+    }
+
+    /// The strongest value among the inputs bound to the control, between 0 and 1
+    /// Keys and mouse buttons are either 0 or 1, analog gamepad buttons report anything in between.
+    pub fn value(&self, control: GameControl) -> f32 {
+        self.bindings
+            .get(control)
+            .iter()
+            .map(|binding| self.binding_value(*binding))
+            .fold(0., f32::max)
+    }
+
+    pub fn binding_value(&self, binding: InputBinding) -> f32 {
+        match binding {
+            InputBinding::Gamepad(button_type) => self
+                .gamepads
+                .iter()
+                .map(|gamepad| {
+                    let button = GamepadButton::new(gamepad, button_type);
+                    // digital buttons have no axis value
+                    self.gamepad_button_axes.get(button).unwrap_or(
+                        if self.gamepad_buttons.pressed(button) {
+                            1.
+                        } else {
+                            0.
+                        },
+                    )
+                })
+                .fold(0., f32::max),
+            _ if self.binding_pressed(binding) => 1.,
+            _ => 0.,
+        }
+    }
+
+    pub fn binding_pressed(&self, binding: InputBinding) -> bool {
+        match binding {
+            InputBinding::Key(key) => self.keyboard.pressed(key),
+            InputBinding::Mouse(button) => self.mouse.pressed(button),
+            InputBinding::Gamepad(button_type) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .pressed(GamepadButton::new(gamepad, button_type))
+            }),
+        }
+    }
+
+    pub fn binding_just_pressed(&self, binding: InputBinding) -> bool {
+        match binding {
+            InputBinding::Key(key) => self.keyboard.just_pressed(key),
+            InputBinding::Mouse(button) => self.mouse.just_pressed(button),
+            InputBinding::Gamepad(button_type) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .just_pressed(GamepadButton::new(gamepad, button_type))
+            }),
+        }
+    }
+
+    /// Left stick deflection of the gamepad pushed furthest, x is right and y is up
+    pub fn movement_stick(&self) -> Vec2 {
+        self.stick(
+            GamepadAxisType::LeftStickX,
+            GamepadAxisType::LeftStickY,
+            self.gamepad_config.movement,
+        )
+    }
+
+    /// Right stick deflection of the gamepad pushed furthest, x is right and y is up
+    pub fn camera_stick(&self) -> Vec2 {
+        self.stick(
+            GamepadAxisType::RightStickX,
+            GamepadAxisType::RightStickY,
+            self.gamepad_config.camera,
+        )
+    }
+
+    fn stick(&self, x: GamepadAxisType, y: GamepadAxisType, response: StickResponse) -> Vec2 {
+        self.gamepads
+            .iter()
+            .map(|gamepad| {
+                let axis = |axis_type| {
+                    self.gamepad_axes
+                        .get(GamepadAxis::new(gamepad, axis_type))
+                        .unwrap_or_default()
+                };
+                response.apply(Vec2::new(axis(x), axis(y)))
+            })
+            .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+            .unwrap_or_default()
+    }
+}
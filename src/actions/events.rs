@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::actions::bindings::ControlInput;
+use crate::actions::game_control::GameControl;
+use crate::config;
+
+const ACTION_BUFFER_FILE: &str = "action_buffer.ron";
+
+/// Sent for every change of a [`GameControl`], no matter which of its inputs caused it
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub struct ActionEvent {
+    pub control: GameControl,
+    pub kind: ActionEventKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActionEventKind {
+    Pressed,
+    /// Sent every frame after the press while the control stays down, with the seconds since the press
+    Held(f32),
+    /// The control was let go after being held for the given seconds
+    Released(f32),
+}
+
+/// Remembers presses for a short time, so a press that comes slightly too early still counts
+/// The window is loaded from `action_buffer.ron` in the `config` folder next to the executable, if it exists.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActionBuffer {
+    /// Seconds a press stays available to [`ActionBuffer::consume`], e.g. a jump pressed right before landing
+    pub window: f32,
+    // seconds since each buffered press
+    #[serde(skip)]
+    presses: HashMap<GameControl, f32>,
+}
+
+impl Default for ActionBuffer {
+    fn default() -> Self {
+        ActionBuffer {
+            window: 0.15,
+            presses: HashMap::default(),
+        }
+    }
+}
+
+impl ActionBuffer {
+    pub fn load() -> Self {
+        config::load(ACTION_BUFFER_FILE).unwrap_or_default()
+    }
+
+    /// Whether the control was pressed within the buffer window, removing the press if so
+    pub fn consume(&mut self, control: GameControl) -> bool {
+        self.presses.remove(&control).is_some()
+    }
+
+    /// Drops every buffered press, e.g. when the game is paused
+    pub fn clear(&mut self) {
+        self.presses.clear();
+    }
+
+    fn tick(&mut self, delta: f32) {
+        let window = self.window;
+        self.presses.retain(|_, age| {
+            *age += delta;
+            *age <= window
+        });
+    }
+}
+
+pub fn emit_action_events(
+    time: Res<Time>,
+    control_input: ControlInput,
+    mut held: Local<HashMap<GameControl, f32>>,
+    mut buffer: ResMut<ActionBuffer>,
+    mut events: EventWriter<ActionEvent>,
+) {
+    buffer.tick(time.delta_seconds());
+    for control in GameControl::ALL {
+        let kind = match (held.get(&control).copied(), control_input.pressed(control)) {
+            (None, true) => {
+                held.insert(control, 0.);
+                buffer.presses.insert(control, 0.);
+                ActionEventKind::Pressed
+            }
+            (Some(duration), true) => {
+                let duration = duration + time.delta_seconds();
+                held.insert(control, duration);
+                ActionEventKind::Held(duration)
+            }
+            (Some(duration), false) => {
+                held.remove(&control);
+                ActionEventKind::Released(duration)
+            }
+            (None, false) => continue,
+        };
+        events.send(ActionEvent { control, kind });
+    }
+}
@@ -1,27 +1,35 @@
-use bevy::prelude::{Input, KeyCode, Res};
+use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum GameControl {
     Up,
     Down,
     Left,
     Right,
+    /// Held to move faster than running
+    Sprint,
+    /// Held to move slower than running
+    Walk,
+    Jump,
+    Interact,
+    Pause,
+    CycleSkybox,
+    /// Shows or hides the physics debug renderer
+    DebugRender,
 }
 
 impl GameControl {
-    pub fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Up => keyboard_input.pressed(KeyCode::W),
-            GameControl::Down => keyboard_input.pressed(KeyCode::R),
-            GameControl::Left => keyboard_input.pressed(KeyCode::A),
-            GameControl::Right => keyboard_input.pressed(KeyCode::S),
-        }
-    }
-}
-
-pub fn get_movement(control: GameControl, input: &Res<Input<KeyCode>>) -> f32 {
-    if control.pressed(input) {
-        1.0
-    } else {
-        0.0
-    }
+    pub const ALL: [GameControl; 11] = [
+        GameControl::Up,
+        GameControl::Down,
+        GameControl::Left,
+        GameControl::Right,
+        GameControl::Sprint,
+        GameControl::Walk,
+        GameControl::Jump,
+        GameControl::Interact,
+        GameControl::Pause,
+        GameControl::CycleSkybox,
+        GameControl::DebugRender,
+    ];
 }
@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+const GAMEPAD_CONFIG_FILE: &str = "gamepad.ron";
+
+/// Shapes raw stick input with a radial dead zone and a response curve
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StickResponse {
+    /// Deflections up to this length are ignored
+    pub dead_zone: f32,
+    /// Deflections from this length on count as fully pushed
+    pub live_zone: f32,
+    /// 1 is linear, higher values give finer control close to the dead zone
+    pub exponent: f32,
+}
+
+impl StickResponse {
+    /// Returns the shaped deflection, with a length between 0 and 1
+    pub fn apply(&self, raw: Vec2) -> Vec2 {
+        let length = raw.length();
+        if length <= self.dead_zone {
+            return Vec2::ZERO;
+        }
+        // rescale so the output starts at zero right outside the dead zone
+        let range = (self.live_zone - self.dead_zone).max(f32::EPSILON);
+        let deflection = ((length - self.dead_zone) / range).min(1.0);
+        raw / length * deflection.powf(self.exponent)
+    }
+}
+
+/// How gamepad sticks drive the player and the camera
+/// Loaded from `gamepad.ron` in the `config` folder next to the executable, if it exists.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    /// Applied to the left stick, which moves the player
+    pub movement: StickResponse,
+    /// Applied to the right stick, which orbits the camera
+    pub camera: StickResponse,
+    /// Camera rotation at full deflection in radians per second
+    /// Scaled by [`GameSettings::camera_sensitivity`](crate::settings::GameSettings)
+    pub camera_speed: f32,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        GamepadConfig {
+            movement: StickResponse {
+                dead_zone: 0.15,
+                live_zone: 0.95,
+                exponent: 1.5,
+            },
+            camera: StickResponse {
+                dead_zone: 0.1,
+                live_zone: 0.95,
+                exponent: 2.0,
+            },
+            camera_speed: 3.0,
+        }
+    }
+}
+
+impl GamepadConfig {
+    pub fn load() -> Self {
+        config::load(GAMEPAD_CONFIG_FILE).unwrap_or_default()
+    }
+}
@@ -1,60 +1,178 @@
+use bevy::ecs::system::SystemParam;
+use bevy::input::mouse::MouseMotion;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 
-use crate::actions::game_control::{get_movement, GameControl};
-use crate::player::Player;
-use crate::GameState;
+use crate::actions::touch::{read_touches, spawn_touch_ui, update_touch_ui, CircleImage};
+use crate::settings::GameSettings;
+use crate::{GameState, PauseState};
+use bevy_third_person_camera::ThirdPersonCamera;
+use serde::{Deserialize, Serialize};
 
+pub use bindings::{ControlInput, InputBinding, KeyBindings};
+pub use events::{emit_action_events, ActionBuffer, ActionEvent, ActionEventKind};
+pub use game_control::GameControl;
+pub use gamepad::GamepadConfig;
+pub use touch::TouchControls;
+
+mod bindings;
+mod events;
 mod game_control;
+mod gamepad;
+mod touch;
 
-pub const FOLLOW_EPSILON: f32 = 5.;
+/// Camera rotation in radians per logical pixel a camera touch is dragged
+pub const TOUCH_CAMERA_SENSITIVITY: f32 = 0.005;
+/// Camera rotation in radians per pixel the mouse moves while the cursor is locked
+pub const MOUSE_CAMERA_SENSITIVITY: f32 = 0.0025;
 
 pub struct ActionsPlugin;
 
-// This plugin listens for keyboard input and converts the input into Actions
+// This plugin listens for the inputs bound in KeyBindings, gamepad sticks, mouse movement and touches and converts them into Actions
 // Actions can then be used as a resource in other systems to act on the player input.
+// On touch devices a virtual joystick and a camera drag area are shown once the first touch is detected.
+// Presses and releases of every GameControl are also sent as ActionEvents and kept in the ActionBuffer.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Actions>().add_systems(
-            Update,
-            set_movement_actions.run_if(in_state(GameState::Playing)),
-        );
+        app.add_event::<ActionEvent>()
+            .init_resource::<Actions>()
+            .insert_resource(ActionBuffer::load())
+            .init_resource::<TouchControls>()
+            .init_resource::<CircleImage>()
+            .insert_resource(KeyBindings::load())
+            .insert_resource(GamepadConfig::load())
+            .add_systems(OnEnter(GameState::Playing), spawn_touch_ui)
+            .add_systems(OnExit(GameState::Playing), reset_actions)
+            .add_systems(
+                Update,
+                (
+                    emit_action_events,
+                    (
+                        read_touches,
+                        (set_movement_actions, set_gait_action, set_camera_action)
+                            .after(read_touches)
+                            .after(emit_action_events)
+                            .run_if(in_state(PauseState::Running)),
+                        update_touch_ui.after(read_touches),
+                    )
+                        .run_if(in_state(GameState::Playing)),
+                    save_key_bindings.run_if(
+                        resource_changed::<KeyBindings>()
+                            .and_then(not(resource_added::<KeyBindings>())),
+                    ),
+                ),
+            );
     }
 }
 
-#[derive(Default, Resource)]
+#[derive(Default, Resource, Clone, Debug, Serialize, Deserialize)]
 pub struct Actions {
+    /// Direction the player wants to move in on the ground, as world space `(x, z)`
+    /// Its length between 0 and 1 is how far the stick is tilted or the touch is dragged.
     pub player_movement: Option<Vec2>,
+    pub gait: Gait,
+    /// How far the camera should orbit the player this frame in radians, x is yaw to the right and y is pitch up
+    pub camera_rotation: Vec2,
+}
+
+/// Speed modifier applied on top of [`Actions::player_movement`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Gait {
+    Walk,
+    #[default]
+    Run,
+    Sprint,
 }
 
 pub fn set_movement_actions(
     mut actions: ResMut<Actions>,
-    keyboard_input: Res<Input<KeyCode>>,
-    touch_input: Res<Touches>,
-    player: Query<&Transform, With<Player>>,
-    camera: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    control_input: ControlInput,
+    touch_controls: Res<TouchControls>,
+    camera: Query<&GlobalTransform, With<ThirdPersonCamera>>,
 ) {
-    let mut player_movement = Vec2::new(
-        get_movement(GameControl::Right, &keyboard_input)
-            - get_movement(GameControl::Left, &keyboard_input),
-        get_movement(GameControl::Up, &keyboard_input)
-            - get_movement(GameControl::Down, &keyboard_input),
-    );
-
-    if let Some(touch_position) = touch_input.first_pressed_position() {
-        let (camera, camera_transform) = camera.single();
-        if let Some(touch_position) = camera.viewport_to_world_2d(camera_transform, touch_position)
-        {
-            let diff = touch_position - player.single().translation.xy();
-            if diff.length() > FOLLOW_EPSILON {
-                player_movement = diff.normalize();
-            }
-        }
-    }
+    let Ok(camera_transform) = camera.get_single() else {
+        actions.player_movement = None;
+        return;
+    };
+
+    // x is right and y is forward, as seen from the camera
+    let input = Vec2::new(
+        control_input.value(GameControl::Right) - control_input.value(GameControl::Left),
+        control_input.value(GameControl::Up) - control_input.value(GameControl::Down),
+    ) + control_input.movement_stick()
+        + touch_controls.movement;
+    let forward = camera_transform.forward().xz().normalize_or_zero();
+    let right = camera_transform.right().xz().normalize_or_zero();
+    let player_movement = right * input.x + forward * input.y;
 
     if player_movement != Vec2::ZERO {
-        actions.player_movement = Some(player_movement.normalize());
+        // pressing two directions at once is not faster
+        actions.player_movement = Some(player_movement.clamp_length_max(1.));
     } else {
         actions.player_movement = None;
     }
 }
+
+pub fn set_gait_action(mut actions: ResMut<Actions>, control_input: ControlInput) {
+    actions.gait = if control_input.pressed(GameControl::Sprint) {
+        Gait::Sprint
+    } else if control_input.pressed(GameControl::Walk) {
+        Gait::Walk
+    } else {
+        Gait::Run
+    };
+}
+
+/// Mouse movement that orbits the camera, which only happens while the camera has locked the cursor
+#[derive(SystemParam)]
+pub struct MouseOrbit<'w, 's> {
+    motion: EventReader<'w, 's, MouseMotion>,
+    camera: Query<'w, 's, &'static ThirdPersonCamera>,
+}
+
+impl MouseOrbit<'_, '_> {
+    /// Distance the mouse moved this frame in pixels, y is down
+    pub fn delta(&mut self) -> Vec2 {
+        let delta = self.motion.read().map(|motion| motion.delta).sum();
+        let locked = self
+            .camera
+            .get_single()
+            .is_ok_and(|camera| camera.cursor_lock_active);
+        if locked {
+            delta
+        } else {
+            Vec2::ZERO
+        }
+    }
+}
+
+pub fn set_camera_action(
+    time: Res<Time>,
+    mut actions: ResMut<Actions>,
+    control_input: ControlInput,
+    mut mouse_orbit: MouseOrbit,
+    gamepad_config: Res<GamepadConfig>,
+    touch_controls: Res<TouchControls>,
+    settings: Res<GameSettings>,
+) {
+    // touch drags and mouse movement are already a distance per frame, while the stick sets a speed
+    let drag = Vec2::new(touch_controls.camera_drag.x, -touch_controls.camera_drag.y);
+    let mouse = mouse_orbit.delta() * Vec2::new(1., -1.);
+    let mut rotation =
+        control_input.camera_stick() * gamepad_config.camera_speed * time.delta_seconds()
+            + drag * TOUCH_CAMERA_SENSITIVITY
+            + mouse * MOUSE_CAMERA_SENSITIVITY;
+    if settings.invert_camera_y {
+        rotation.y = -rotation.y;
+    }
+    actions.camera_rotation = rotation * settings.camera_sensitivity;
+}
+
+fn reset_actions(mut actions: ResMut<Actions>, mut action_buffer: ResMut<ActionBuffer>) {
+    *actions = Actions::default();
+    action_buffer.clear();
+}
+
+fn save_key_bindings(key_bindings: Res<KeyBindings>) {
+    key_bindings.save();
+}
@@ -0,0 +1,198 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::window::PrimaryWindow;
+
+use crate::{DespawnOnExit, GameState};
+
+/// Distance in logical pixels a joystick touch has to be dragged to move at full speed
+pub const JOYSTICK_RADIUS: f32 = 60.0;
+const KNOB_RADIUS: f32 = 25.0;
+// distance of the idle joystick from the bottom left corner of the window
+const JOYSTICK_MARGIN: f32 = 40.0;
+
+/// State of the on-screen touch controls
+/// Touches starting on the left half of the window drive a virtual joystick,
+/// touches starting on the right half drag the camera around the player.
+#[derive(Resource, Default)]
+pub struct TouchControls {
+    /// Set by the first touch, the on-screen controls stay hidden until then
+    pub detected: bool,
+    /// Joystick deflection, x is right and y is up, with a length of up to 1
+    pub movement: Vec2,
+    /// How far the camera touch moved this frame, in logical pixels with y pointing down
+    pub camera_drag: Vec2,
+    // touch id and the position it started at
+    joystick: Option<(u64, Vec2)>,
+    camera: Option<u64>,
+}
+
+#[derive(Component)]
+pub(super) struct TouchUi;
+
+#[derive(Component)]
+pub(super) struct JoystickBase;
+
+#[derive(Component)]
+pub(super) struct JoystickKnob;
+
+/// White disk used to draw the joystick
+#[derive(Resource)]
+pub(super) struct CircleImage(Handle<Image>);
+
+impl FromWorld for CircleImage {
+    fn from_world(world: &mut World) -> Self {
+        const SIZE: u32 = 64;
+        let center = (SIZE as f32 - 1.) / 2.;
+        let data = (0..SIZE * SIZE)
+            .flat_map(|index| {
+                let offset = Vec2::new((index % SIZE) as f32, (index / SIZE) as f32) - center;
+                // one pixel of anti-aliasing at the edge
+                let alpha = (SIZE as f32 / 2. - offset.length()).clamp(0., 1.);
+                [255, 255, 255, (alpha * 255.) as u8]
+            })
+            .collect();
+        let image = Image::new(
+            Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        CircleImage(world.resource_mut::<Assets<Image>>().add(image))
+    }
+}
+
+pub(super) fn read_touches(
+    mut controls: ResMut<TouchControls>,
+    touches: Res<Touches>,
+    window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let half_width = window.get_single().map_or(0., |window| window.width() / 2.);
+    for touch in touches.iter_just_pressed() {
+        controls.detected = true;
+        if touch.position().x < half_width {
+            if controls.joystick.is_none() {
+                controls.joystick = Some((touch.id(), touch.position()));
+            }
+        } else if controls.camera.is_none() {
+            controls.camera = Some(touch.id());
+        }
+    }
+
+    let joystick = controls
+        .joystick
+        .and_then(|(id, origin)| Some((touches.get_pressed(id)?, origin)));
+    controls.movement = match joystick {
+        Some((touch, origin)) => {
+            let offset = touch.position() - origin;
+            (Vec2::new(offset.x, -offset.y) / JOYSTICK_RADIUS).clamp_length_max(1.)
+        }
+        None => {
+            controls.joystick = None;
+            Vec2::ZERO
+        }
+    };
+
+    let camera = controls.camera.and_then(|id| touches.get_pressed(id));
+    controls.camera_drag = match camera {
+        Some(touch) => touch.delta(),
+        None => {
+            controls.camera = None;
+            Vec2::ZERO
+        }
+    };
+}
+
+pub(super) fn spawn_touch_ui(mut commands: Commands, circle: Res<CircleImage>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            TouchUi,
+            DespawnOnExit(GameState::Playing),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ImageBundle {
+                        style: Style {
+                            width: Val::Px(2. * JOYSTICK_RADIUS),
+                            height: Val::Px(2. * JOYSTICK_RADIUS),
+                            position_type: PositionType::Absolute,
+                            ..default()
+                        },
+                        image: circle.0.clone().into(),
+                        background_color: Color::rgba(1., 1., 1., 0.15).into(),
+                        ..default()
+                    },
+                    JoystickBase,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        ImageBundle {
+                            style: Style {
+                                width: Val::Px(2. * KNOB_RADIUS),
+                                height: Val::Px(2. * KNOB_RADIUS),
+                                position_type: PositionType::Absolute,
+                                ..default()
+                            },
+                            image: circle.0.clone().into(),
+                            background_color: Color::rgba(1., 1., 1., 0.5).into(),
+                            ..default()
+                        },
+                        JoystickKnob,
+                    ));
+                });
+        });
+}
+
+pub(super) fn update_touch_ui(
+    controls: Res<TouchControls>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut root: Query<&mut Visibility, With<TouchUi>>,
+    mut base: Query<&mut Style, (With<JoystickBase>, Without<JoystickKnob>)>,
+    mut knob: Query<&mut Style, (With<JoystickKnob>, Without<JoystickBase>)>,
+) {
+    for mut visibility in &mut root {
+        *visibility = if controls.detected {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    if !controls.detected {
+        return;
+    }
+
+    // the joystick follows the touch that started it and rests in the bottom left corner otherwise
+    let center = match controls.joystick {
+        Some((_, origin)) => origin,
+        None => {
+            let height = window.get_single().map_or(0., Window::height);
+            Vec2::new(
+                JOYSTICK_MARGIN + JOYSTICK_RADIUS,
+                height - JOYSTICK_MARGIN - JOYSTICK_RADIUS,
+            )
+        }
+    };
+    for mut style in &mut base {
+        style.left = Val::Px(center.x - JOYSTICK_RADIUS);
+        style.top = Val::Px(center.y - JOYSTICK_RADIUS);
+    }
+    let knob_offset = Vec2::new(controls.movement.x, -controls.movement.y) * JOYSTICK_RADIUS;
+    for mut style in &mut knob {
+        style.left = Val::Px(JOYSTICK_RADIUS - KNOB_RADIUS + knob_offset.x);
+        style.top = Val::Px(JOYSTICK_RADIUS - KNOB_RADIUS + knob_offset.y);
+    }
+}
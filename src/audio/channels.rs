@@ -0,0 +1,150 @@
+use crate::settings::GameSettings;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_kira_audio::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Background music, crossfaded between tracks
+#[derive(Resource)]
+pub struct MusicChannel;
+
+/// Sound effects of the player and the stage
+#[derive(Resource)]
+pub struct SfxChannel;
+
+/// Looping environment sounds
+#[derive(Resource)]
+pub struct AmbienceChannel;
+
+/// Menu sounds, which keep playing while the game is paused
+#[derive(Resource)]
+pub struct UiChannel;
+
+/// The audio channel a sound is played on, each channel has its own volume and mute setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AudioChannelKind {
+    Music,
+    Sfx,
+    Ambience,
+    Ui,
+}
+
+/// A sound gameplay code can play by its logical name
+#[derive(Clone, Debug)]
+pub struct Sound {
+    pub source: Handle<AudioSource>,
+    pub channel: AudioChannelKind,
+    /// Volume of the sound itself, between 0 and 1, before the channel volume is applied
+    pub volume: f32,
+    /// Whether [`SoundPlayer::play`] loops the sound
+    pub looping: bool,
+}
+
+/// All sounds by their logical name
+#[derive(Resource, Default)]
+pub struct SoundLibrary(pub HashMap<String, Sound>);
+
+// Every instance the `SoundPlayer` started, with the volume it plays at before its channel volume.
+// Kira only knows the volume of an instance, which includes the channel volume, so the instances are
+// updated one by one when the channel volumes change. The handles keep the instances around until
+// they are stopped and forgotten.
+#[derive(Resource, Default)]
+pub(super) struct PlayingSounds(pub(super) HashMap<Handle<AudioInstance>, PlayingSound>);
+
+pub(super) struct PlayingSound {
+    pub(super) channel: AudioChannelKind,
+    pub(super) volume: f32,
+}
+
+// panning of kira instances goes from 0 (left) to 1 (right)
+pub(super) const CENTER_PANNING: f32 = 0.5;
+
+/// How [`SoundPlayer::play_with`] plays a sound
+#[derive(Clone, Copy, Debug)]
+pub struct PlayOptions {
+    /// Overrides whether the sound loops, `None` keeps the sound's own setting
+    pub looped: Option<bool>,
+    /// Scales the volume of the sound
+    pub gain: f32,
+    /// Between 0 (left) and 1 (right)
+    pub panning: f32,
+    /// Time to fade in from silence
+    pub fade_in: Option<Duration>,
+}
+
+impl Default for PlayOptions {
+    fn default() -> Self {
+        PlayOptions {
+            looped: None,
+            gain: 1.,
+            panning: CENTER_PANNING,
+            fade_in: None,
+        }
+    }
+}
+
+/// Plays sounds from the [`SoundLibrary`] on their channel
+#[derive(SystemParam)]
+pub struct SoundPlayer<'w> {
+    library: Res<'w, SoundLibrary>,
+    settings: Res<'w, GameSettings>,
+    playing: ResMut<'w, PlayingSounds>,
+    music: Res<'w, AudioChannel<MusicChannel>>,
+    sfx: Res<'w, AudioChannel<SfxChannel>>,
+    ambience: Res<'w, AudioChannel<AmbienceChannel>>,
+    ui: Res<'w, AudioChannel<UiChannel>>,
+}
+
+impl<'w> SoundPlayer<'w> {
+    /// Plays the sound once or in a loop, as declared for it
+    /// Returns `None` and logs a warning if there is no sound with the given name.
+    pub fn play(&mut self, name: &str) -> Option<Handle<AudioInstance>> {
+        self.play_with(name, PlayOptions::default())
+    }
+
+    /// Plays the sound once, even if it is declared as looping
+    pub fn play_once(&mut self, name: &str) -> Option<Handle<AudioInstance>> {
+        self.play_with(
+            name,
+            PlayOptions {
+                looped: Some(false),
+                ..default()
+            },
+        )
+    }
+
+    pub fn play_with(&mut self, name: &str, options: PlayOptions) -> Option<Handle<AudioInstance>> {
+        let Some(sound) = self.library.0.get(name) else {
+            warn!("There is no sound called {name:?}");
+            return None;
+        };
+        let source = sound.source.clone();
+        let mut command = match sound.channel {
+            AudioChannelKind::Music => self.music.play(source),
+            AudioChannelKind::Sfx => self.sfx.play(source),
+            AudioChannelKind::Ambience => self.ambience.play(source),
+            AudioChannelKind::Ui => self.ui.play(source),
+        };
+        if options.looped.unwrap_or(sound.looping) {
+            command.looped();
+        }
+        if let Some(duration) = options.fade_in {
+            command.fade_in(AudioTween::linear(duration));
+        }
+        let volume = sound.volume * options.gain;
+        let instance = command
+            .with_volume((volume * self.settings.channel_volume(sound.channel)) as f64)
+            .with_panning(options.panning as f64)
+            .handle();
+        self.playing.0.insert(
+            instance.clone(),
+            PlayingSound {
+                channel: sound.channel,
+                volume,
+            },
+        );
+        Some(instance)
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::KinematicCharacterControllerOutput;
+
+use crate::audio::SoundPlayer;
+use crate::player::{move_player, GroundContact, GroundState, Player, PlayerJumped, PlayerLanded};
+use crate::stage::Surface;
+use crate::{GameState, PauseState};
+
+pub struct FootstepsPlugin;
+
+/// This plugin plays footsteps while the player moves on the ground, picked by the [`Surface`] below it,
+/// and a sound for every jump and hard landing
+impl Plugin for FootstepsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SurfaceSounds>().add_systems(
+            Update,
+            play_movement_sounds
+                .after(move_player)
+                .run_if(in_state(GameState::Playing).and_then(in_state(PauseState::Running))),
+        );
+    }
+}
+
+/// Names of the footstep sounds for every surface, one of them is picked at random for each step
+#[derive(Resource)]
+pub struct SurfaceSounds(pub HashMap<Surface, Vec<String>>);
+
+impl Default for SurfaceSounds {
+    fn default() -> Self {
+        let sounds = |surface: &str| {
+            (1..=2)
+                .map(|variant| format!("footstep_{surface}_{variant}"))
+                .collect()
+        };
+        SurfaceSounds(
+            [
+                (Surface::Stone, sounds("stone")),
+                (Surface::Grass, sounds("grass")),
+                (Surface::Metal, sounds("metal")),
+                (Surface::Wood, sounds("wood")),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+}
+
+// meters between two steps at standstill, growing with the speed so running takes longer strides
+const BASE_STEP_LENGTH: f32 = 0.5;
+const STEP_LENGTH_PER_SPEED: f32 = 0.1;
+// falling faster than this in meters per second makes an audible landing
+const LANDING_SPEED: f32 = 2.0;
+
+// What the player did since the last frame
+#[derive(SystemParam)]
+struct PlayerEvents<'w, 's> {
+    jumped: EventReader<'w, 's, PlayerJumped>,
+    landed: EventReader<'w, 's, PlayerLanded>,
+}
+
+fn play_movement_sounds(
+    time: Res<Time>,
+    mut sound_player: SoundPlayer,
+    surface_sounds: Res<SurfaceSounds>,
+    // meters walked since the last step
+    mut distance: Local<f32>,
+    mut events: PlayerEvents,
+    player: Query<
+        (
+            &KinematicCharacterControllerOutput,
+            &GroundState,
+            &GroundContact,
+        ),
+        With<Player>,
+    >,
+    surfaces: Query<&Surface>,
+) {
+    let Ok((output, ground_state, contact)) = player.get_single() else {
+        return;
+    };
+    let grounded = *ground_state == GroundState::Grounded;
+    let surface = contact
+        .0
+        .and_then(|ground| surfaces.get(ground).ok())
+        .copied()
+        .unwrap_or_default();
+    let footstep = |sound_player: &mut SoundPlayer| {
+        if let Some(names) = surface_sounds.0.get(&surface) {
+            if !names.is_empty() {
+                sound_player.play_once(&names[rand::random::<usize>() % names.len()]);
+            }
+        }
+    };
+
+    for _ in events.jumped.read() {
+        sound_player.play("jump");
+    }
+    for event in events.landed.read() {
+        if event.speed > LANDING_SPEED {
+            sound_player.play("land");
+            footstep(&mut sound_player);
+            *distance = 0.;
+        }
+    }
+
+    if grounded && time.delta_seconds() > 0. {
+        // how far the player actually got, walking into a wall makes no steps
+        let moved = output.effective_translation.xz().length();
+        let speed = moved / time.delta_seconds();
+        *distance += moved;
+        let step_length = BASE_STEP_LENGTH + STEP_LENGTH_PER_SPEED * speed;
+        if *distance >= step_length {
+            *distance = 0.;
+            footstep(&mut sound_player);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AssetPath, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::{BoxedFuture, HashSet};
+use bevy_asset_loader::prelude::*;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::audio::AudioChannelKind;
+
+/// Key of the manifest's sound files in the dynamic assets, `AudioAssets::sounds` is loaded from it
+pub const SOUNDS_KEY: &str = "sounds";
+
+/// All sounds of the game, described in a `.audio.ron` asset file
+/// Every entry becomes a [`Sound`](crate::audio::Sound) in the `SoundLibrary` once loading is done.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioManifest {
+    pub sounds: Vec<SoundEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoundEntry {
+    /// Logical name gameplay code plays the sound by
+    pub name: String,
+    /// Path of the audio file, relative to the assets folder
+    pub path: String,
+    pub channel: AudioChannelKind,
+    /// Default volume of the sound, between 0 and 1
+    #[serde(default = "full_volume")]
+    pub volume: f32,
+    #[serde(default)]
+    pub looping: bool,
+}
+
+fn full_volume() -> f32 {
+    1.
+}
+
+impl DynamicAssetCollection for AudioManifest {
+    fn register(&self, dynamic_assets: &mut DynamicAssets) {
+        dynamic_assets.register_asset(
+            SOUNDS_KEY,
+            Box::new(StandardDynamicAsset::Files {
+                paths: self.sounds.iter().map(|sound| sound.path.clone()).collect(),
+            }),
+        );
+    }
+}
+
+impl AudioManifest {
+    // everything wrong with the entries themselves, the audio files are checked by the loader
+    fn problems(&self) -> Vec<AudioManifestProblem> {
+        let mut problems = Vec::new();
+        let mut names = HashSet::new();
+        for sound in &self.sounds {
+            if !names.insert(sound.name.as_str()) {
+                problems.push(AudioManifestProblem::DuplicateName(sound.name.clone()));
+            }
+            if !(0.0..=1.0).contains(&sound.volume) {
+                problems.push(AudioManifestProblem::InvalidVolume {
+                    name: sound.name.clone(),
+                    volume: sound.volume,
+                });
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AudioManifestError {
+    #[error("could not read audio manifest: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse audio manifest: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("invalid audio manifest: {}", list_problems(.0))]
+    Invalid(Vec<AudioManifestProblem>),
+}
+
+/// A single mistake in the audio manifest, all of them are reported together
+#[derive(Debug, Error)]
+pub enum AudioManifestProblem {
+    #[error("sound name '{0}' is used by more than one sound")]
+    DuplicateName(String),
+    #[error("sound '{name}' has volume {volume}, which is not between 0 and 1")]
+    InvalidVolume { name: String, volume: f32 },
+    #[error("audio file '{path}' of sound '{name}' is missing")]
+    MissingFile { name: String, path: String },
+}
+
+fn list_problems(problems: &[AudioManifestProblem]) -> String {
+    problems
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+pub struct AudioManifestLoader {
+    // asset sources, to look for the audio files without loading them
+    asset_server: AssetServer,
+}
+
+impl FromWorld for AudioManifestLoader {
+    fn from_world(world: &mut World) -> Self {
+        AudioManifestLoader {
+            asset_server: world.resource::<AssetServer>().clone(),
+        }
+    }
+}
+
+impl AudioManifestLoader {
+    // opening the file is enough to know that it exists, it is read by the audio loader later
+    async fn file_exists(&self, path: &str) -> bool {
+        let path = AssetPath::parse(path);
+        let Ok(source) = self.asset_server.get_source(path.source()) else {
+            return false;
+        };
+        let exists = source.reader().read(path.path()).await.is_ok();
+        exists
+    }
+}
+
+impl AssetLoader for AudioManifestLoader {
+    type Asset = AudioManifest;
+    type Settings = ();
+    type Error = AudioManifestError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let manifest: AudioManifest = ron::de::from_bytes(&bytes)?;
+            let mut problems = manifest.problems();
+            // a missing file would otherwise keep the loading state waiting forever
+            for sound in &manifest.sounds {
+                if !self.file_exists(&sound.path).await {
+                    problems.push(AudioManifestProblem::MissingFile {
+                        name: sound.name.clone(),
+                        path: sound.path.clone(),
+                    });
+                }
+            }
+            if !problems.is_empty() {
+                return Err(AudioManifestError::Invalid(problems));
+            }
+            Ok(manifest)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["audio.ron"]
+    }
+}
@@ -0,0 +1,136 @@
+use crate::loading::AudioAssets;
+use crate::settings::GameSettings;
+use crate::{GameState, PauseState};
+use bevy::prelude::*;
+use bevy_kira_audio::prelude::*;
+
+use channels::{PlayingSound, PlayingSounds};
+use footsteps::FootstepsPlugin;
+use manifest::AudioManifestLoader;
+pub use manifest::{AudioManifest, SOUNDS_KEY};
+use music::MusicPlugin;
+pub use music::Playlist;
+pub use spatial::AudioEmitter;
+use spatial::SpatialAudioPlugin;
+
+pub use channels::{
+    AmbienceChannel, AudioChannelKind, MusicChannel, PlayOptions, SfxChannel, Sound, SoundLibrary,
+    SoundPlayer, UiChannel,
+};
+
+mod channels;
+mod footsteps;
+mod manifest;
+mod music;
+mod spatial;
+
+pub struct InternalAudioPlugin;
+
+// This plugin is responsible to control the game audio
+// Sounds are played by name through the `SoundPlayer` on the music, SFX, ambience or UI channel,
+// whose volumes follow the `GameSettings`.
+// The sounds themselves are declared in the `AudioManifest`, which is validated while loading.
+impl Plugin for InternalAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            AudioPlugin,
+            FootstepsPlugin,
+            SpatialAudioPlugin,
+            MusicPlugin,
+        ))
+        .add_audio_channel::<MusicChannel>()
+        .add_audio_channel::<SfxChannel>()
+        .add_audio_channel::<AmbienceChannel>()
+        .add_audio_channel::<UiChannel>()
+        .init_asset::<AudioManifest>()
+        .init_asset_loader::<AudioManifestLoader>()
+        .init_resource::<SoundLibrary>()
+        .init_resource::<PlayingSounds>()
+        .add_systems(
+            OnExit(GameState::Loading),
+            fill_sound_library.run_if(resource_exists::<AudioAssets>()),
+        )
+        .add_systems(OnEnter(PauseState::Paused), pause_game_audio)
+        .add_systems(OnExit(PauseState::Paused), resume_game_audio)
+        .add_systems(
+            Update,
+            apply_channel_volumes.run_if(resource_changed::<GameSettings>()),
+        )
+        .add_systems(Last, forget_stopped_sounds);
+    }
+}
+
+fn fill_sound_library(
+    mut library: ResMut<SoundLibrary>,
+    audio_assets: Res<AudioAssets>,
+    manifests: Res<Assets<AudioManifest>>,
+) {
+    let Some(manifest) = manifests.get(&audio_assets.manifest) else {
+        error!("The audio manifest is not loaded, the game has no sounds");
+        return;
+    };
+    for entry in &manifest.sounds {
+        let Some(source) = audio_assets.sounds.get(&entry.path) else {
+            warn!(
+                "No audio file loaded for sound '{}' at '{}'",
+                entry.name, entry.path
+            );
+            continue;
+        };
+        library.0.insert(
+            entry.name.clone(),
+            Sound {
+                source: source.clone(),
+                channel: entry.channel,
+                volume: entry.volume,
+                looping: entry.looping,
+            },
+        );
+    }
+}
+
+// `AudioChannel::set_volume` would replace the volume of every instance on the channel,
+// so each instance gets its own volume multiplied by the new channel volume instead
+fn apply_channel_volumes(
+    settings: Res<GameSettings>,
+    playing: Res<PlayingSounds>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    for (handle, PlayingSound { channel, volume }) in &playing.0 {
+        // instances that have not started yet keep the channel volume they were played with
+        if let Some(instance) = audio_instances.get_mut(handle) {
+            instance.set_volume(
+                (volume * settings.channel_volume(*channel)) as f64,
+                AudioTween::default(),
+            );
+        }
+    }
+}
+
+fn forget_stopped_sounds(
+    mut playing: ResMut<PlayingSounds>,
+    audio_instances: Res<Assets<AudioInstance>>,
+) {
+    playing.0.retain(|handle, _| {
+        !audio_instances
+            .get(handle)
+            .is_some_and(|instance| instance.state() == PlaybackState::Stopped)
+    });
+}
+
+// the sounds of the frozen game are held until it continues, music and menu sounds keep playing
+fn pause_game_audio(
+    sfx: Res<AudioChannel<SfxChannel>>,
+    ambience: Res<AudioChannel<AmbienceChannel>>,
+) {
+    sfx.pause();
+    ambience.pause();
+}
+
+fn resume_game_audio(
+    sfx: Res<AudioChannel<SfxChannel>>,
+    ambience: Res<AudioChannel<AmbienceChannel>>,
+) {
+    sfx.resume();
+    ambience.resume();
+}
@@ -0,0 +1,323 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_kira_audio::prelude::{
+    AudioChannel, AudioControl, AudioInstance, AudioTween, PlaybackState,
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::audio::{fill_sound_library, MusicChannel, PlayOptions, SoundLibrary, SoundPlayer};
+use crate::loading::StageAssets;
+use crate::skybox::{Cubemap, Environment};
+use crate::stage::{CurrentStage, StageDefinition, Zone, ZoneEntered, ZoneExited, ZoneKind};
+use crate::GameState;
+
+pub struct MusicPlugin;
+
+/// This plugin plays the menu music in `GameState::Menu` and picks the music of `GameState::Playing`
+/// from the music zone the player is in, the stage's playlist or the skybox's, in that order
+/// Every change of playlist crossfades into the new music.
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MusicPlaylists>()
+            .init_resource::<MusicPlayer>()
+            .init_resource::<MusicZone>()
+            .add_systems(
+                OnExit(GameState::Loading),
+                validate_playlists
+                    .after(fill_sound_library)
+                    .run_if(resource_exists::<StageAssets>()),
+            )
+            .add_systems(Update, (track_music_zone, play_music).chain());
+    }
+}
+
+/// Tracks from the `SoundLibrary`, played one after another
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Playlist {
+    pub tracks: Vec<String>,
+    #[serde(default)]
+    pub mode: PlaylistMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum PlaylistMode {
+    /// Plays the tracks in order and starts over after the last one
+    #[default]
+    Loop,
+    /// Plays a random track after every track, never the same one twice in a row
+    Shuffle,
+    /// Keeps repeating the first track
+    RepeatTrack,
+}
+
+impl Playlist {
+    pub fn new(tracks: &[&str], mode: PlaylistMode) -> Self {
+        Playlist {
+            tracks: tracks.iter().map(|track| track.to_string()).collect(),
+            mode,
+        }
+    }
+
+    // the track to play after `current`
+    fn next(&self, current: usize) -> usize {
+        let count = self.tracks.len().max(1);
+        match self.mode {
+            PlaylistMode::Loop => (current + 1) % count,
+            PlaylistMode::Shuffle if count > 1 => {
+                (current + 1 + rand::random::<usize>() % (count - 1)) % count
+            }
+            PlaylistMode::Shuffle | PlaylistMode::RepeatTrack => current,
+        }
+    }
+}
+
+/// The music of the menu and of every skybox environment
+#[derive(Resource)]
+pub struct MusicPlaylists {
+    pub menu: Playlist,
+    pub environments: HashMap<Environment, Playlist>,
+}
+
+impl Default for MusicPlaylists {
+    fn default() -> Self {
+        MusicPlaylists {
+            menu: Playlist::new(&["menu"], PlaylistMode::Loop),
+            environments: [
+                (
+                    Environment::City,
+                    Playlist::new(&["city", "courtyard"], PlaylistMode::Loop),
+                ),
+                (
+                    Environment::Church,
+                    Playlist::new(&["sacred"], PlaylistMode::RepeatTrack),
+                ),
+                (
+                    Environment::Forest,
+                    Playlist::new(&["wilds", "sacred"], PlaylistMode::Shuffle),
+                ),
+                (
+                    Environment::TownSquare,
+                    Playlist::new(&["city"], PlaylistMode::RepeatTrack),
+                ),
+                (
+                    Environment::Mountainside,
+                    Playlist::new(&["wilds"], PlaylistMode::RepeatTrack),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+}
+
+// Tracks are only looked up when they start playing, so every playlist is checked against the
+// sound library as soon as it is filled and all unknown tracks are reported at once
+fn validate_playlists(
+    library: Res<SoundLibrary>,
+    playlists: Res<MusicPlaylists>,
+    stages: Res<StageAssets>,
+    definitions: Res<Assets<StageDefinition>>,
+) {
+    let mut tracks: Vec<(String, &String)> = playlists
+        .menu
+        .tracks
+        .iter()
+        .map(|track| ("the menu".to_string(), track))
+        .collect();
+    for (environment, playlist) in &playlists.environments {
+        tracks.extend(
+            playlist
+                .tracks
+                .iter()
+                .map(|track| (format!("the {environment:?} skybox"), track)),
+        );
+    }
+    for (index, handle) in stages.stages.iter().enumerate() {
+        let Some(definition) = definitions.get(handle) else {
+            continue;
+        };
+        if let Some(music) = &definition.music {
+            tracks.extend(
+                music
+                    .tracks
+                    .iter()
+                    .map(|track| (format!("stage #{index}"), track)),
+            );
+        }
+        for zone in &definition.zones {
+            if let ZoneKind::Music { track } = &zone.kind {
+                tracks.push((format!("zone '{}' of stage #{index}", zone.id), track));
+            }
+        }
+    }
+
+    let unknown: Vec<String> = tracks
+        .into_iter()
+        .filter(|(_, track)| !library.0.contains_key(track.as_str()))
+        .map(|(source, track)| format!("'{track}' of {source}"))
+        .collect();
+    if !unknown.is_empty() {
+        error!(
+            "Music tracks missing from the sound library, they will not play: {}",
+            unknown.join(", ")
+        );
+    }
+}
+
+const CROSSFADE: Duration = Duration::from_secs(2);
+
+#[derive(Resource, Default)]
+struct MusicPlayer {
+    playlist: Option<Playlist>,
+    // index into the playlist's tracks
+    track: usize,
+    instance: Option<Handle<AudioInstance>>,
+}
+
+// The music zone the player is in and its track
+#[derive(Resource, Default)]
+struct MusicZone(Option<(Entity, String)>);
+
+fn track_music_zone(
+    mut music_zone: ResMut<MusicZone>,
+    mut entered: EventReader<ZoneEntered>,
+    mut exited: EventReader<ZoneExited>,
+    zones: Query<(), With<Zone>>,
+) {
+    for event in entered.read() {
+        if let ZoneKind::Music { track } = &event.kind {
+            music_zone.0 = Some((event.zone, track.clone()));
+        }
+    }
+    for event in exited.read() {
+        if music_zone
+            .0
+            .as_ref()
+            .is_some_and(|(zone, _)| *zone == event.zone)
+        {
+            music_zone.0 = None;
+        }
+    }
+    // the zone is gone after changing or leaving the stage
+    if let Some((zone, _)) = music_zone.0 {
+        if !zones.contains(zone) {
+            music_zone.0 = None;
+        }
+    }
+}
+
+// Everything the music of the current state is picked from
+#[derive(SystemParam)]
+struct MusicSources<'w> {
+    playlists: Res<'w, MusicPlaylists>,
+    music_zone: Res<'w, MusicZone>,
+    cubemap: Option<Res<'w, Cubemap>>,
+    stages: Option<Res<'w, StageAssets>>,
+    current_stage: Res<'w, CurrentStage>,
+    definitions: Res<'w, Assets<StageDefinition>>,
+}
+
+impl MusicSources<'_> {
+    // the music zone's track, then the stage's playlist, then the skybox's
+    fn stage_playlist(&self) -> Option<Playlist> {
+        self.music_zone
+            .0
+            .as_ref()
+            .map(|(_, track)| Playlist {
+                tracks: vec![track.clone()],
+                mode: PlaylistMode::RepeatTrack,
+            })
+            .or_else(|| {
+                self.stages
+                    .as_ref()
+                    .and_then(|stages| self.current_stage.definition(stages, &self.definitions))
+                    .and_then(|definition| definition.music.clone())
+            })
+            .or_else(|| {
+                self.cubemap.as_ref().and_then(|cubemap| {
+                    self.playlists
+                        .environments
+                        .get(&cubemap.environment)
+                        .cloned()
+                })
+            })
+    }
+}
+
+fn play_music(
+    game_state: Res<State<GameState>>,
+    sources: MusicSources,
+    mut player: ResMut<MusicPlayer>,
+    mut sound_player: SoundPlayer,
+    music_channel: Res<AudioChannel<MusicChannel>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    let playlist = match game_state.get() {
+        GameState::Loading | GameState::LoadingFailed => None,
+        GameState::Menu => Some(sources.playlists.menu.clone()),
+        // keeps the music of the stage that is about to be entered again
+        GameState::Restarting => return,
+        GameState::Playing => sources.stage_playlist(),
+    };
+
+    if playlist != player.playlist {
+        if let Some(instance) = player
+            .instance
+            .take()
+            .and_then(|handle| audio_instances.get_mut(&handle))
+        {
+            instance.stop(AudioTween::linear(CROSSFADE));
+        }
+        player.track = match &playlist {
+            Some(playlist) if playlist.mode == PlaylistMode::Shuffle => {
+                rand::random::<usize>() % playlist.tracks.len().max(1)
+            }
+            _ => 0,
+        };
+        player.playlist = playlist;
+        start_track(&mut player, &mut sound_player, Some(CROSSFADE));
+        return;
+    }
+
+    // move on once the current track has ended
+    let ended = player
+        .instance
+        .as_ref()
+        .is_some_and(|handle| matches!(music_channel.state(handle), PlaybackState::Stopped));
+    if ended {
+        if let Some(next) = player
+            .playlist
+            .as_ref()
+            .map(|playlist| playlist.next(player.track))
+        {
+            player.track = next;
+        }
+        start_track(&mut player, &mut sound_player, None);
+    }
+}
+
+fn start_track(
+    player: &mut MusicPlayer,
+    sound_player: &mut SoundPlayer,
+    fade_in: Option<Duration>,
+) {
+    let Some(track) = player
+        .playlist
+        .as_ref()
+        .and_then(|playlist| playlist.tracks.get(player.track))
+    else {
+        player.instance = None;
+        return;
+    };
+    player.instance = sound_player.play_with(
+        track,
+        PlayOptions {
+            looped: Some(false),
+            fade_in,
+            ..default()
+        },
+    );
+}
@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy::utils::HashMap;
+use bevy_kira_audio::prelude::{AudioInstance, AudioTween};
+use bevy_third_person_camera::ThirdPersonCamera;
+
+use crate::audio::channels::{PlayingSounds, CENTER_PANNING};
+use crate::audio::{PlayOptions, SoundLibrary, SoundPlayer};
+use crate::settings::GameSettings;
+
+pub struct SpatialAudioPlugin;
+
+/// This plugin plays the sound of every [`AudioEmitter`] from the emitter's position
+/// The camera is the listener: emitters get quieter with distance and are panned to the side they are on
+impl Plugin for SpatialAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EmitterInstances>().add_systems(
+            PostUpdate,
+            (start_emitters, stop_removed_emitters, update_emitters)
+                .chain()
+                .after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Plays a sound from the [`SoundLibrary`] at the entity's position for as long as the component exists
+/// Looping sounds keep playing, others are played once when the component is added.
+#[derive(Component, Clone, Debug)]
+pub struct AudioEmitter {
+    pub sound: String,
+    /// Distance in meters at which the sound fades out completely
+    pub range: f32,
+}
+
+// playing instance of each emitter
+#[derive(Resource, Default)]
+struct EmitterInstances(HashMap<Entity, EmitterInstance>);
+
+// the instance with the gain and panning it was last given, which are only sent to kira again when they change
+struct EmitterInstance {
+    handle: Handle<AudioInstance>,
+    gain: f32,
+    panning: f32,
+}
+
+// how far a sound can be panned to one side, fully panned sounds are lost on the other ear
+const MAX_PANNING: f32 = 0.4;
+
+// Volume factor and panning of a sound at `position` heard from `listener`
+fn spatialize(listener: &GlobalTransform, position: Vec3, range: f32) -> (f32, f32) {
+    let offset = position - listener.translation();
+    let distance = offset.length();
+    // quadratic falloff, which reaches zero exactly at the range
+    let gain = (1. - distance / range.max(f32::EPSILON))
+        .clamp(0., 1.)
+        .powi(2);
+    let side = offset.normalize_or_zero().dot(listener.right());
+    (gain, CENTER_PANNING + side * MAX_PANNING)
+}
+
+fn start_emitters(
+    mut sound_player: SoundPlayer,
+    mut instances: ResMut<EmitterInstances>,
+    listener: Query<&GlobalTransform, With<ThirdPersonCamera>>,
+    emitters: Query<(Entity, &AudioEmitter, &GlobalTransform), Added<AudioEmitter>>,
+) {
+    for (entity, emitter, transform) in &emitters {
+        // the first frame is already spatialized, so far away emitters do not start at full volume
+        let (gain, panning) = listener
+            .get_single()
+            .map_or((0., CENTER_PANNING), |listener| {
+                spatialize(listener, transform.translation(), emitter.range)
+            });
+        let options = PlayOptions {
+            gain,
+            panning,
+            ..default()
+        };
+        if let Some(handle) = sound_player.play_with(&emitter.sound, options) {
+            instances.0.insert(
+                entity,
+                EmitterInstance {
+                    handle,
+                    gain,
+                    panning,
+                },
+            );
+        }
+    }
+}
+
+fn stop_removed_emitters(
+    mut removed: RemovedComponents<AudioEmitter>,
+    mut instances: ResMut<EmitterInstances>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    for entity in removed.read() {
+        let Some(EmitterInstance { handle, .. }) = instances.0.remove(&entity) else {
+            continue;
+        };
+        if let Some(instance) = audio_instances.get_mut(&handle) {
+            instance.stop(AudioTween::default());
+        }
+    }
+}
+
+fn update_emitters(
+    library: Res<SoundLibrary>,
+    settings: Res<GameSettings>,
+    mut instances: ResMut<EmitterInstances>,
+    mut playing: ResMut<PlayingSounds>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    listener: Query<&GlobalTransform, With<ThirdPersonCamera>>,
+    emitters: Query<(Entity, &AudioEmitter, &GlobalTransform)>,
+) {
+    let Ok(listener) = listener.get_single() else {
+        return;
+    };
+    for (entity, emitter, transform) in &emitters {
+        let (Some(emitter_instance), Some(sound)) =
+            (instances.0.get_mut(&entity), library.0.get(&emitter.sound))
+        else {
+            continue;
+        };
+        let Some(instance) = audio_instances.get_mut(&emitter_instance.handle) else {
+            continue;
+        };
+        let (gain, panning) = spatialize(listener, transform.translation(), emitter.range);
+        if gain != emitter_instance.gain {
+            let volume = sound.volume * gain;
+            // channel volume changes start from the gain the emitter is heard with now
+            if let Some(playing_sound) = playing.0.get_mut(&emitter_instance.handle) {
+                playing_sound.volume = volume;
+            }
+            instance.set_volume(
+                (volume * settings.channel_volume(sound.channel)) as f64,
+                AudioTween::default(),
+            );
+            emitter_instance.gain = gain;
+        }
+        if panning != emitter_instance.panning {
+            instance.set_panning(panning as f64, AudioTween::default());
+            emitter_instance.panning = panning;
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::player::Player;
+use crate::stage::RespawnPlayer;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier3d::prelude::*;
+
+pub struct CollisionPlugin;
+
+/// This plugin turns Rapier collision events and character controller hits into [`PlayerCollision`] events
+/// Gameplay reacts to them through the [`Hazard`], [`Pickup`] and [`Trigger`] markers on stage entities
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerCollision>().add_systems(
+            Update,
+            (
+                emit_player_collisions,
+                (touch_hazards, collect_pickups, log_triggers).after(emit_player_collisions),
+            )
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionKind {
+    Started,
+    Stopped,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlayerCollision {
+    pub other: Entity,
+    pub kind: CollisionKind,
+    /// World space contact normal pointing from `other` towards the player
+    /// `None` for sensors and when the contact is already gone
+    pub normal: Option<Vec3>,
+}
+
+/// Sends the player back to the last checkpoint when touched
+#[derive(Component)]
+pub struct Hazard;
+
+/// Despawned when the player touches it
+#[derive(Component)]
+pub struct Pickup;
+
+/// Reports [`PlayerCollision`] events without any built-in reaction, the contacts are logged at debug level
+#[derive(Component)]
+pub struct Trigger;
+
+// Entities touched by the player, split by where we learned about the contact.
+// The character controller reports what blocked the last move, Rapier reports sensor overlaps
+// and any other contact pairs involving the player collider.
+#[derive(Default)]
+pub struct Touching {
+    controller: HashMap<Entity, Vec3>,
+    physics: HashSet<Entity>,
+}
+
+impl Touching {
+    fn all(&self) -> HashSet<Entity> {
+        self.controller
+            .keys()
+            .chain(self.physics.iter())
+            .copied()
+            .collect()
+    }
+}
+
+pub fn emit_player_collisions(
+    mut touching: Local<Touching>,
+    rapier_context: Res<RapierContext>,
+    mut collision_events: EventReader<CollisionEvent>,
+    player_q: Query<(Entity, Option<&KinematicCharacterControllerOutput>), With<Player>>,
+    mut player_collisions: EventWriter<PlayerCollision>,
+) {
+    let Ok((player, output)) = player_q.get_single() else {
+        // the player is gone with everything it touched, e.g. after leaving the game
+        *touching = Touching::default();
+        collision_events.clear();
+        return;
+    };
+    let before = touching.all();
+
+    touching.controller = output
+        .map(|output| {
+            output
+                .collisions
+                .iter()
+                .filter_map(|hit| {
+                    // penetrating hits have no normal
+                    let details = hit.toi.details?;
+                    Some((hit.entity, -(hit.character_rotation * details.normal1)))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    for event in collision_events.read() {
+        match *event {
+            CollisionEvent::Started(a, b, _) if a == player => {
+                touching.physics.insert(b);
+            }
+            CollisionEvent::Started(a, b, _) if b == player => {
+                touching.physics.insert(a);
+            }
+            CollisionEvent::Stopped(a, b, _) if a == player => {
+                touching.physics.remove(&b);
+            }
+            CollisionEvent::Stopped(a, b, _) if b == player => {
+                touching.physics.remove(&a);
+            }
+            _ => {}
+        }
+    }
+
+    let after = touching.all();
+    for &other in after.difference(&before) {
+        let normal = touching
+            .controller
+            .get(&other)
+            .copied()
+            .or_else(|| contact_normal(&rapier_context, player, other));
+        player_collisions.send(PlayerCollision {
+            other,
+            kind: CollisionKind::Started,
+            normal,
+        });
+    }
+    for &other in before.difference(&after) {
+        player_collisions.send(PlayerCollision {
+            other,
+            kind: CollisionKind::Stopped,
+            normal: None,
+        });
+    }
+}
+
+fn contact_normal(rapier_context: &RapierContext, player: Entity, other: Entity) -> Option<Vec3> {
+    let pair = rapier_context.contact_pair(player, other)?;
+    let normal = pair.manifolds().next()?.normal();
+    // manifold normals point from the first collider of the pair to the second
+    if pair.collider1() == player {
+        Some(-normal)
+    } else {
+        Some(normal)
+    }
+}
+
+fn touch_hazards(
+    mut player_collisions: EventReader<PlayerCollision>,
+    hazards: Query<(), With<Hazard>>,
+    mut respawn: EventWriter<RespawnPlayer>,
+) {
+    for collision in player_collisions.read() {
+        if collision.kind == CollisionKind::Started && hazards.contains(collision.other) {
+            respawn.send(RespawnPlayer);
+        }
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    mut player_collisions: EventReader<PlayerCollision>,
+    pickups: Query<(), With<Pickup>>,
+) {
+    for collision in player_collisions.read() {
+        if collision.kind == CollisionKind::Started && pickups.contains(collision.other) {
+            commands.entity(collision.other).despawn_recursive();
+        }
+    }
+}
+
+fn log_triggers(
+    mut player_collisions: EventReader<PlayerCollision>,
+    triggers: Query<(), With<Trigger>>,
+) {
+    for collision in player_collisions.read() {
+        if triggers.contains(collision.other) {
+            debug!(
+                "Trigger {:?} {:?} with normal {:?}",
+                collision.other, collision.kind, collision.normal
+            );
+        }
+    }
+}
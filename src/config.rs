@@ -0,0 +1,59 @@
+use bevy::log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+
+// user configuration lives next to the game, so it is easy to find and reset
+const CONFIG_DIRECTORY: &str = "config";
+
+// the config directory beside the executable, or in the working directory if its location is unknown
+fn config_directory() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|executable| {
+            executable
+                .parent()
+                .map(|parent| parent.join(CONFIG_DIRECTORY))
+        })
+        .unwrap_or_else(|| PathBuf::from(CONFIG_DIRECTORY))
+}
+
+fn config_path(file_name: &str) -> PathBuf {
+    config_directory().join(file_name)
+}
+
+/// Reads a RON config file, returning `None` if it does not exist or is invalid
+/// There is no file system on the web, so nothing is ever loaded there.
+pub fn load<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    if cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    let path = config_path(file_name);
+    let contents = std::fs::read_to_string(&path).ok()?;
+    match ron::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            warn!("Ignoring invalid config file {}: {error}", path.display());
+            None
+        }
+    }
+}
+
+/// Writes a value as a RON config file, creating the config directory if needed
+pub fn save<T: Serialize>(file_name: &str, value: &T) {
+    if cfg!(target_arch = "wasm32") {
+        return;
+    }
+    let path = config_path(file_name);
+    let result = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())
+        .and_then(|contents| {
+            std::fs::create_dir_all(config_directory())
+                .and_then(|_| std::fs::write(&path, contents))
+                .map_err(|error| error.to_string())
+        });
+    match result {
+        Ok(()) => info!("Saved {}", path.display()),
+        Err(error) => warn!("Failed to save config file {}: {error}", path.display()),
+    }
+}
@@ -2,24 +2,37 @@
 
 mod actions;
 mod audio;
+mod collision;
+mod config;
 mod loading;
 mod menu;
+mod physics;
 mod player;
+mod replay;
+mod settings;
 mod skybox;
 mod stage;
 
+pub use replay::run_headless;
+
 use crate::actions::ActionsPlugin;
 use crate::audio::InternalAudioPlugin;
+use crate::collision::CollisionPlugin;
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
+use crate::physics::PhysicsPlugin;
 use crate::player::PlayerPlugin;
+use crate::replay::ReplayPlugin;
+use crate::settings::{GameSettings, SettingsPlugin};
 use crate::skybox::ThirdDimensionPlugin;
 use crate::stage::StagePlugin;
 
 use bevy::app::App;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::input::InputPlugin;
 use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
 use bevy_third_person_camera::ThirdPersonCameraPlugin;
 
 // This example game uses States to separate logic
@@ -34,21 +47,45 @@ enum GameState {
     Playing,
     // Here the menu is drawn and waiting for player interaction
     Menu,
+    // Passed through for a single frame to leave and enter `Playing` again, which restarts the stage
+    Restarting,
+    // Reached instead of `Menu` when an asset could not be loaded, only the error is shown
+    LoadingFailed,
+}
+
+// Entities with this component are despawned, with their children, when the given state is exited
+#[derive(Component)]
+struct DespawnOnExit(GameState);
+
+// Whether the game logic of `GameState::Playing` runs or is frozen behind the pause menu
+#[derive(States, Default, Clone, Copy, Eq, PartialEq, Debug, Hash)]
+enum PauseState {
+    #[default]
+    Running,
+    Paused,
 }
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<GameState>().add_plugins((
-            LoadingPlugin,
-            MenuPlugin,
-            ThirdDimensionPlugin,
-            StagePlugin,
-            // ActionsPlugin,
-            // InternalAudioPlugin,
-            PlayerPlugin,
-        ));
+        app.add_state::<GameState>()
+            .add_state::<PauseState>()
+            .add_plugins((
+                LoadingPlugin,
+                MenuPlugin,
+                PhysicsPlugin::default(),
+                ThirdDimensionPlugin,
+                StagePlugin,
+                ActionsPlugin,
+                InternalAudioPlugin,
+                PlayerPlugin,
+                CollisionPlugin,
+                ReplayPlugin,
+                SettingsPlugin,
+                ThirdPersonCameraPlugin,
+                StateCleanupPlugin,
+            ));
 
         #[cfg(debug_assertions)]
         {
@@ -56,3 +93,141 @@ impl Plugin for GamePlugin {
         }
     }
 }
+
+struct StateCleanupPlugin;
+
+/// This plugin despawns the `DespawnOnExit` entities of every state and passes through `GameState::Restarting`
+impl Plugin for StateCleanupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Restarting), restart);
+        for state in [
+            GameState::Loading,
+            GameState::Playing,
+            GameState::Menu,
+            GameState::Restarting,
+            GameState::LoadingFailed,
+        ] {
+            app.add_systems(OnExit(state.clone()), despawn_on_exit(state));
+        }
+    }
+}
+
+fn despawn_on_exit(state: GameState) -> impl FnMut(Commands, Query<(Entity, &DespawnOnExit)>) {
+    move |mut commands, entities| {
+        for (entity, despawn) in &entities {
+            if despawn.0 == state {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn restart(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Playing);
+}
+
+// The game's states and the engine parts its logic needs, without a window, rendering or audio
+// Headless replays run in it, and the tests build on it.
+fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        InputPlugin,
+        ScenePlugin,
+    ))
+    .init_asset::<Mesh>()
+    .init_asset::<StandardMaterial>()
+    .init_asset::<Image>()
+    .init_resource::<GameSettings>()
+    .add_state::<GameState>()
+    .add_state::<PauseState>();
+    app
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loading::{Skyboxes, StageAssets, TextureAssets};
+    use crate::replay::ReplayState;
+    use crate::stage::StageDefinition;
+    use bevy::time::TimeUpdateStrategy;
+
+    // the game without loading, rendering and audio, with placeholders for the loaded assets
+    fn game_app() -> App {
+        let mut app = headless_app();
+        app.init_resource::<ReplayState>()
+            // one physics step per frame, like a replay
+            .insert_resource(TimeUpdateStrategy::ManualDuration(
+                std::time::Duration::from_secs_f32(PhysicsPlugin::default().timestep),
+            ))
+            .add_plugins((
+                MenuPlugin,
+                PhysicsPlugin::default(),
+                ThirdDimensionPlugin,
+                StagePlugin,
+                ActionsPlugin,
+                PlayerPlugin,
+                CollisionPlugin,
+                StateCleanupPlugin,
+            ));
+
+        let image = app
+            .world
+            .resource_mut::<Assets<Image>>()
+            .add(Image::default());
+        app.insert_resource(TextureAssets {
+            bevy: image.clone(),
+            github: image.clone(),
+        })
+        .insert_resource(Skyboxes {
+            city: image.clone(),
+            church: image.clone(),
+            town_square: image.clone(),
+            mountainside: image.clone(),
+            forest: image,
+        });
+        let definition: StageDefinition =
+            ron::de::from_str(include_str!("../assets/stages/arena.stage.ron"))
+                .expect("stage definition should parse");
+        let stage = app
+            .world
+            .resource_mut::<Assets<StageDefinition>>()
+            .add(definition);
+        app.insert_resource(StageAssets {
+            stages: vec![stage],
+        });
+        app.finish();
+        app.cleanup();
+        app
+    }
+
+    // switches the state and lets the game run for a few frames in it
+    fn enter(app: &mut App, state: GameState) -> usize {
+        app.world.resource_mut::<NextState<GameState>>().set(state);
+        for _ in 0..3 {
+            app.update();
+        }
+        app.world.entities().len() as usize
+    }
+
+    #[test]
+    fn cycling_states_does_not_leak_entities() {
+        let mut app = game_app();
+        let menu = enter(&mut app, GameState::Menu);
+        let playing = enter(&mut app, GameState::Playing);
+        assert!(playing > menu);
+
+        for _ in 0..3 {
+            assert_eq!(enter(&mut app, GameState::Restarting), playing);
+            assert_eq!(
+                *app.world.resource::<State<GameState>>().get(),
+                GameState::Playing
+            );
+            assert_eq!(enter(&mut app, GameState::Menu), menu);
+            assert_eq!(enter(&mut app, GameState::Playing), playing);
+        }
+    }
+}
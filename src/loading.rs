@@ -1,31 +1,124 @@
-use crate::GameState;
+use crate::audio::{AudioManifest, SOUNDS_KEY};
+use crate::stage::StageDefinition;
+use crate::{DespawnOnExit, GameState};
+use bevy::asset::LoadState;
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use bevy_asset_loader::prelude::*;
 use bevy_kira_audio::AudioSource;
 
+/// Describes every sound of the game
+const AUDIO_MANIFEST: &str = "audio/sounds.audio.ron";
+
 pub struct LoadingPlugin;
 
 /// This plugin loads all assets using [`AssetLoader`] from a third party bevy plugin
 /// Alternatively you can write the logic to load assets yourself
 /// If interested, take a look at <https://bevy-cheatbook.github.io/features/assets.html>
+/// Any asset that fails to load ends up in `GameState::LoadingFailed`
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
         app.add_loading_state(
-            LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu),
+            LoadingState::new(GameState::Loading)
+                .continue_to_state(GameState::Menu)
+                .on_failure_continue_to_state(GameState::LoadingFailed),
+        )
+        .add_dynamic_collection_to_loading_state::<_, AudioManifest>(
+            GameState::Loading,
+            AUDIO_MANIFEST,
         )
         .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
         .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading)
-        .add_collection_to_loading_state::<_, Skyboxes>(GameState::Loading);
+        .add_collection_to_loading_state::<_, Skyboxes>(GameState::Loading)
+        .add_collection_to_loading_state::<_, StageAssets>(GameState::Loading)
+        .add_systems(
+            Update,
+            fail_on_invalid_manifest.run_if(in_state(GameState::Loading)),
+        )
+        .add_systems(OnEnter(GameState::LoadingFailed), show_loading_error);
     }
 }
 
+// The asset loader waits for dynamic collections without checking for failures,
+// so a manifest that does not load would keep the game loading forever
+fn fail_on_invalid_manifest(
+    asset_server: Res<AssetServer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let failed = asset_server
+        .get_handle_untyped(AUDIO_MANIFEST)
+        .is_some_and(|manifest| asset_server.load_state(manifest.id()) == LoadState::Failed);
+    if failed {
+        next_state.set(GameState::LoadingFailed);
+    }
+}
+
+fn show_loading_error(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle::default(),
+        DespawnOnExit(GameState::LoadingFailed),
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "The game could not load all of its assets, the log lists what went wrong",
+            TextStyle {
+                font_size: 30.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::all(Val::Auto),
+            ..default()
+        }),
+        DespawnOnExit(GameState::LoadingFailed),
+    ));
+}
+
 // the following asset collections will be loaded during the State `GameState::Loading`
 // when done loading, they will be inserted as resources (see <https://github.com/NiklasEi/bevy_asset_loader>)
 
-#[derive(AssetCollection, Resource)]
+/// The audio manifest and its sound files
+/// The collection is not derived, so that the key of the sound files can come from `SOUNDS_KEY`.
+#[derive(Resource)]
 pub struct AudioAssets {
-    #[asset(path = "audio/flying.ogg")]
-    pub flying: Handle<AudioSource>,
+    /// Describes every sound, see `audio/sounds.audio.ron`
+    pub manifest: Handle<AudioManifest>,
+    /// The manifest's audio files by their path
+    pub sounds: HashMap<String, Handle<AudioSource>>,
+}
+
+impl AssetCollection for AudioAssets {
+    fn create(world: &mut World) -> Self {
+        let sounds = world.resource_scope(|world, dynamic_assets: Mut<DynamicAssets>| {
+            let files = dynamic_assets
+                .get_asset(SOUNDS_KEY)
+                .expect("the audio manifest registers its sound files");
+            match files.build(world) {
+                Ok(DynamicAssetType::Collection(handles)) => handles
+                    .into_iter()
+                    .filter_map(|handle| {
+                        let path = handle.path()?.path().to_str()?.replace('\\', "/");
+                        Some((path, handle.typed()))
+                    })
+                    .collect(),
+                result => panic!("The sound files did not resolve to a collection: {result:?}"),
+            }
+        });
+        AudioAssets {
+            manifest: world.resource::<AssetServer>().load(AUDIO_MANIFEST),
+            sounds,
+        }
+    }
+
+    fn load(world: &mut World) -> Vec<UntypedHandle> {
+        let asset_server = world.resource::<AssetServer>();
+        let mut handles = vec![asset_server.load::<AudioManifest>(AUDIO_MANIFEST).untyped()];
+        if let Some(files) = world.resource::<DynamicAssets>().get_asset(SOUNDS_KEY) {
+            handles.extend(files.load(asset_server));
+        }
+        handles
+    }
 }
 
 #[derive(AssetCollection, Resource)]
@@ -49,3 +142,13 @@ pub struct Skyboxes {
     #[asset(path = "textures/skyboxes/MountainPath/cubemap.png")]
     pub forest: Handle<Image>,
 }
+
+#[derive(AssetCollection, Resource)]
+pub struct StageAssets {
+    /// All playable stages, in the order they are visited
+    #[asset(
+        paths("stages/arena.stage.ron", "stages/courtyard.stage.ron"),
+        collection(typed)
+    )]
+    pub stages: Vec<Handle<StageDefinition>>,
+}
@@ -8,11 +8,15 @@ use bevy::DefaultPlugins;
 use bevy_editor_pls::prelude::*;
 use bevy_game::GamePlugin; // ToDo: Replace bevy_game with your new crate name.
 use std::io::Cursor;
+use std::process::ExitCode;
 use winit::window::Icon;
 
-fn main() {
+fn main() -> ExitCode {
+    // `--headless --replay <file>` checks a replay without opening a window
+    if std::env::args().any(|arg| arg == "--headless") {
+        return bevy_game::run_headless(std::env::args().skip(1));
+    }
     App::new()
-        .insert_resource(Msaa::Off)
         .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -31,6 +35,7 @@ fn main() {
         .add_plugins(GamePlugin)
         .add_systems(Startup, set_window_icon)
         .run();
+    ExitCode::SUCCESS
 }
 
 // Sets the icon on windows and X11
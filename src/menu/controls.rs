@@ -0,0 +1,366 @@
+use crate::actions::{GameControl, InputBinding, KeyBindings};
+use crate::menu::navigation::navigate_with_gamepad;
+use crate::menu::{cleanup_screen, spawn_button, Back, ButtonColors, MenuScreen};
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+use std::collections::BTreeSet;
+
+pub struct ControlsMenuPlugin;
+
+/// This plugin draws the controls screen of the menu, where every [`GameControl`] can be rebound
+/// Changed bindings are saved by the `ActionsPlugin`
+impl Plugin for ControlsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rebinding>()
+            .add_systems(OnEnter(MenuScreen::Controls), setup_controls_screen)
+            .add_systems(
+                Update,
+                (
+                    click_controls_buttons,
+                    capture_rebind,
+                    show_rebind_prompt,
+                    update_binding_labels,
+                )
+                    .chain()
+                    .after(navigate_with_gamepad)
+                    .run_if(in_state(MenuScreen::Controls)),
+            )
+            .add_systems(
+                OnExit(MenuScreen::Controls),
+                (
+                    cleanup_screen::<ControlsScreen>,
+                    cleanup_screen::<RebindPrompt>,
+                    stop_rebinding,
+                ),
+            );
+    }
+}
+
+/// The control waiting for its new input, if any
+#[derive(Resource, Default)]
+pub(super) struct Rebinding(Option<GameControl>);
+
+impl Rebinding {
+    pub(super) fn is_active(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+#[derive(Component)]
+struct ControlsScreen;
+
+#[derive(Component)]
+struct RebindButton(GameControl);
+
+#[derive(Component)]
+struct BindingLabel(GameControl);
+
+#[derive(Component)]
+struct ConflictLabel(GameControl);
+
+#[derive(Component)]
+struct ResetBindings(fn() -> KeyBindings);
+
+// Covers the whole screen while waiting for an input, so the click does not reach any button
+#[derive(Component)]
+struct RebindPrompt;
+
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const CONFLICT_COLOR: Color = Color::rgb(0.9, 0.3, 0.3);
+
+fn setup_controls_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(6.),
+                    ..default()
+                },
+                ..default()
+            },
+            ControlsScreen,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Controls",
+                TextStyle {
+                    font_size: 40.0,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+            for control in GameControl::ALL {
+                spawn_control_row(children, control);
+            }
+            children
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(10.),
+                        margin: UiRect::top(Val::Px(20.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_button(
+                        parent,
+                        "Reset to defaults",
+                        ResetBindings(KeyBindings::default),
+                    );
+                    spawn_button(
+                        parent,
+                        "QWERTY defaults",
+                        ResetBindings(KeyBindings::qwerty),
+                    );
+                    spawn_button(parent, "Back", Back);
+                });
+        });
+}
+
+fn spawn_control_row(children: &mut ChildBuilder, control: GameControl) {
+    children
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    format!("{control:?}"),
+                    TextStyle {
+                        font_size: 25.0,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    width: Val::Px(100.0),
+                    ..default()
+                }),
+            );
+            let button_colors = ButtonColors::default();
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(360.0),
+                            height: Val::Px(36.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: button_colors.normal.into(),
+                        ..default()
+                    },
+                    button_colors,
+                    RebindButton(control),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 20.0,
+                                color: TEXT_COLOR,
+                                ..default()
+                            },
+                        ),
+                        BindingLabel(control),
+                    ));
+                });
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: CONFLICT_COLOR,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    width: Val::Px(220.0),
+                    ..default()
+                }),
+                ConflictLabel(control),
+            ));
+        });
+}
+
+fn click_controls_buttons(
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+    interaction_query: Query<
+        (&Interaction, Option<&RebindButton>, Option<&ResetBindings>),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    if rebinding.0.is_some() {
+        return;
+    }
+    for (interaction, rebind, reset) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(rebind) = rebind {
+            rebinding.0 = Some(rebind.0);
+        } else if let Some(reset) = reset {
+            *key_bindings = (reset.0)();
+        }
+    }
+}
+
+fn capture_rebind(
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+    keyboard: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    // the click that started rebinding must not become the new binding
+    if rebinding.is_changed() {
+        return;
+    }
+    let Some(control) = rebinding.0 else {
+        return;
+    };
+    // Escape and gamepad East cancel, so they can only be bound in the config file
+    let cancelled = keyboard.just_pressed(KeyCode::Escape)
+        || gamepad_buttons
+            .get_just_pressed()
+            .any(|button| button.button_type == GamepadButtonType::East);
+    if cancelled {
+        rebinding.0 = None;
+        return;
+    }
+    let binding = keyboard
+        .get_just_pressed()
+        .next()
+        .map(|key| InputBinding::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|button| InputBinding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| InputBinding::Gamepad(button.button_type))
+        });
+    if let Some(binding) = binding {
+        key_bindings.bind(control, binding);
+        rebinding.0 = None;
+    }
+}
+
+fn show_rebind_prompt(
+    mut commands: Commands,
+    rebinding: Res<Rebinding>,
+    prompt: Query<Entity, With<RebindPrompt>>,
+) {
+    if !rebinding.is_changed() {
+        return;
+    }
+    for entity in &prompt {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(control) = rebinding.0 else {
+        return;
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.8).into(),
+                focus_policy: FocusPolicy::Block,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            RebindPrompt,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                format!(
+                    "Press a key, mouse or gamepad button for {control:?}\n\
+                     Escape or gamepad East cancels"
+                ),
+                TextStyle {
+                    font_size: 30.0,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn update_binding_labels(
+    key_bindings: Res<KeyBindings>,
+    mut binding_labels: Query<(&BindingLabel, &mut Text), Without<ConflictLabel>>,
+    mut conflict_labels: Query<(&ConflictLabel, &mut Text), Without<BindingLabel>>,
+    added: Query<(), Added<ControlsScreen>>,
+) {
+    if !key_bindings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (label, mut text) in &mut binding_labels {
+        text.sections[0].value = key_bindings
+            .get(label.0)
+            .iter()
+            .map(describe_binding)
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+    let conflicts = key_bindings.conflicts();
+    for (label, mut text) in &mut conflict_labels {
+        let shared_with: BTreeSet<GameControl> = conflicts
+            .iter()
+            .filter(|(_, controls)| controls.contains(&label.0))
+            .flat_map(|(_, controls)| controls.iter().copied())
+            .filter(|control| *control != label.0)
+            .collect();
+        text.sections[0].value = if shared_with.is_empty() {
+            String::new()
+        } else {
+            let names: Vec<String> = shared_with
+                .iter()
+                .map(|control| format!("{control:?}"))
+                .collect();
+            format!("Conflicts with {}", names.join(", "))
+        };
+    }
+}
+
+fn describe_binding(binding: &InputBinding) -> String {
+    match binding {
+        InputBinding::Key(key) => format!("Key {key:?}"),
+        InputBinding::Mouse(button) => format!("Mouse {button:?}"),
+        InputBinding::Gamepad(button) => format!("Pad {button:?}"),
+    }
+}
+
+pub(super) fn is_rebinding(rebinding: Res<Rebinding>) -> bool {
+    rebinding.is_active()
+}
+
+fn stop_rebinding(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
@@ -1,19 +1,66 @@
 use crate::loading::TextureAssets;
-use crate::GameState;
+use crate::menu::controls::{is_rebinding, ControlsMenuPlugin};
+use crate::menu::navigation::{navigate_with_gamepad, MenuFocus};
+use crate::menu::pause::PauseMenuPlugin;
+use crate::menu::settings::SettingsMenuPlugin;
+use crate::{DespawnOnExit, GameState, PauseState};
 use bevy::prelude::*;
 
+mod controls;
+mod navigation;
+mod pause;
+mod settings;
+
 pub struct MenuPlugin;
 
-/// This plugin is responsible for the game menu (containing only one button...)
+/// This plugin is responsible for the game menu
 /// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited
+/// Its screens are switched through the `MenuScreen` state and its buttons can be navigated with a gamepad
+/// The pause menu shares those screens while the game is paused
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::Menu), setup_menu)
-            .add_systems(Update, click_play_button.run_if(in_state(GameState::Menu)))
+        app.add_state::<MenuScreen>()
+            .init_resource::<MenuFocus>()
+            .add_plugins((ControlsMenuPlugin, PauseMenuPlugin, SettingsMenuPlugin))
+            .add_systems(OnEnter(GameState::Menu), setup_menu)
+            .add_systems(OnEnter(MenuScreen::Main), setup_main_screen)
+            .add_systems(OnExit(MenuScreen::Main), cleanup_screen::<Menu>)
+            .add_systems(
+                Update,
+                (
+                    navigate_with_gamepad.run_if(not(is_rebinding)),
+                    click_play_button.after(navigate_with_gamepad),
+                )
+                    .run_if(not(in_state(MenuScreen::Closed))),
+            )
             .add_systems(OnExit(GameState::Menu), cleanup_menu);
     }
 }
 
+#[derive(States, Default, Clone, Copy, Eq, PartialEq, Debug, Hash)]
+enum MenuScreen {
+    // Outside of `GameState::Menu` and while the game is not paused
+    #[default]
+    Closed,
+    Main,
+    Pause,
+    Settings,
+    Controls,
+}
+
+impl MenuScreen {
+    // The screen `Back` returns to, if any
+    // The settings are opened from the main menu or the pause menu and go back to where they were opened from
+    fn back(self, pause_state: &PauseState) -> Option<MenuScreen> {
+        match (self, pause_state) {
+            (MenuScreen::Controls, _) => Some(MenuScreen::Settings),
+            (MenuScreen::Settings, PauseState::Running) => Some(MenuScreen::Main),
+            (MenuScreen::Settings, PauseState::Paused) => Some(MenuScreen::Pause),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Component)]
 struct ButtonColors {
     normal: Color,
@@ -35,9 +82,17 @@ struct Menu;
 #[derive(Component)]
 struct MenuCamera;
 
-fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
+fn setup_menu(mut commands: Commands, mut next_screen: ResMut<NextState<MenuScreen>>) {
     info!("menu");
-    commands.spawn((Camera2dBundle::default(), MenuCamera));
+    commands.spawn((
+        Camera2dBundle::default(),
+        MenuCamera,
+        DespawnOnExit(GameState::Menu),
+    ));
+    next_screen.set(MenuScreen::Main);
+}
+
+fn setup_main_screen(mut commands: Commands, textures: Res<TextureAssets>) {
     commands
         .spawn((
             NodeBundle {
@@ -81,6 +136,33 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                         },
                     ));
                 });
+            children
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(140.0),
+                            height: Val::Px(50.0),
+                            margin: UiRect::top(Val::Px(10.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: ButtonColors::default().normal.into(),
+                        ..Default::default()
+                    },
+                    ButtonColors::default(),
+                    ChangeScreen(MenuScreen::Settings),
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Settings",
+                        TextStyle {
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                            ..default()
+                        },
+                    ));
+                });
         });
     commands
         .spawn((
@@ -178,30 +260,81 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
         });
 }
 
+fn spawn_button(parent: &mut ChildBuilder, label: &str, action: impl Component) {
+    let button_colors = ButtonColors::default();
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    min_width: Val::Px(220.0),
+                    height: Val::Px(50.0),
+                    padding: UiRect::horizontal(Val::Px(15.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: button_colors.normal.into(),
+                ..default()
+            },
+            button_colors,
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 25.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+        });
+}
+
 #[derive(Component)]
 struct ChangeState(GameState);
 
+#[derive(Component)]
+struct ChangeScreen(MenuScreen);
+
+// Returns to the previous screen, see `MenuScreen::back`
+#[derive(Component)]
+struct Back;
+
 #[derive(Component)]
 struct OpenLink(&'static str);
 
 fn click_play_button(
     mut next_state: ResMut<NextState<GameState>>,
+    mut next_screen: ResMut<NextState<MenuScreen>>,
+    screen: Res<State<MenuScreen>>,
+    pause_state: Res<State<PauseState>>,
     mut interaction_query: Query<
         (
             &Interaction,
             &mut BackgroundColor,
             &ButtonColors,
             Option<&ChangeState>,
+            Option<&ChangeScreen>,
+            Option<&Back>,
             Option<&OpenLink>,
         ),
         (Changed<Interaction>, With<Button>),
     >,
 ) {
-    for (interaction, mut color, button_colors, change_state, open_link) in &mut interaction_query {
+    for (interaction, mut color, button_colors, change_state, change_screen, back, open_link) in
+        &mut interaction_query
+    {
         match *interaction {
             Interaction::Pressed => {
                 if let Some(state) = change_state {
                     next_state.set(state.0.clone());
+                } else if let Some(screen) = change_screen {
+                    next_screen.set(screen.0);
+                } else if back.is_some() {
+                    if let Some(previous) = screen.get().back(pause_state.get()) {
+                        next_screen.set(previous);
+                    }
                 } else if let Some(link) = open_link {
                     if let Err(error) = webbrowser::open(link.0) {
                         warn!("Failed to open link {error:?}");
@@ -218,14 +351,12 @@ fn click_play_button(
     }
 }
 
-fn cleanup_menu(
-    mut commands: Commands,
-    menu: Query<Entity, With<Menu>>,
-    mut camera_2d: Query<&mut Camera, With<MenuCamera>>,
-) {
-    for entity in menu.iter() {
+fn cleanup_screen<T: Component>(mut commands: Commands, screen: Query<Entity, With<T>>) {
+    for entity in screen.iter() {
         commands.entity(entity).despawn_recursive();
     }
-    let mut cam = camera_2d.single_mut();
-    cam.is_active = !cam.is_active;
+}
+
+fn cleanup_menu(mut next_screen: ResMut<NextState<MenuScreen>>) {
+    next_screen.set(MenuScreen::Closed);
 }
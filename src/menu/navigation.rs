@@ -0,0 +1,120 @@
+use crate::actions::{ControlInput, InputBinding};
+use crate::menu::{ButtonColors, MenuScreen};
+use crate::PauseState;
+use bevy::prelude::*;
+
+/// The button selected with the gamepad, if any
+#[derive(Resource, Default)]
+pub(super) struct MenuFocus(Option<Entity>);
+
+// State of the gamepad between frames
+#[derive(Default)]
+pub(super) struct GamepadNavigation {
+    // the button that was pressed last frame and is released again
+    pressed: Option<Entity>,
+    // the stick moves the focus once per push
+    stick_engaged: bool,
+}
+
+// Moves the focus between the visible buttons in reading order, presses the focused one on South and
+// goes back to the previous screen on East.
+// A gamepad press is reported as `Interaction::Pressed` for a single frame, like a mouse click.
+pub(super) fn navigate_with_gamepad(
+    mut focus: ResMut<MenuFocus>,
+    mut gamepad: Local<GamepadNavigation>,
+    control_input: ControlInput,
+    screen: Res<State<MenuScreen>>,
+    pause_state: Res<State<PauseState>>,
+    mut next_screen: ResMut<NextState<MenuScreen>>,
+    mut buttons: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &ViewVisibility,
+            &mut Interaction,
+            &mut BackgroundColor,
+            &ButtonColors,
+        ),
+        With<Button>,
+    >,
+) {
+    if let Some(entity) = gamepad.pressed.take() {
+        if let Ok((_, _, _, mut interaction, _, _)) = buttons.get_mut(entity) {
+            interaction.set_if_neq(Interaction::None);
+        }
+    }
+
+    let gamepad_just_pressed =
+        |button| control_input.binding_just_pressed(InputBinding::Gamepad(button));
+    let stick = control_input.movement_stick();
+    let stick_pushed = stick.length() > 0.5;
+    let stick_step = if stick_pushed && !gamepad.stick_engaged {
+        if stick.y.abs() >= stick.x.abs() {
+            -stick.y.signum()
+        } else {
+            stick.x.signum()
+        }
+    } else {
+        0.
+    };
+    gamepad.stick_engaged = stick_pushed;
+
+    let step = if gamepad_just_pressed(GamepadButtonType::DPadUp)
+        || gamepad_just_pressed(GamepadButtonType::DPadLeft)
+        || stick_step < 0.
+    {
+        -1
+    } else if gamepad_just_pressed(GamepadButtonType::DPadDown)
+        || gamepad_just_pressed(GamepadButtonType::DPadRight)
+        || stick_step > 0.
+    {
+        1
+    } else {
+        0
+    };
+
+    if gamepad_just_pressed(GamepadButtonType::East) {
+        if let Some(previous) = screen.get().back(pause_state.get()) {
+            next_screen.set(previous);
+            return;
+        }
+    }
+
+    let mut visible: Vec<(Entity, Vec3)> = buttons
+        .iter()
+        .filter(|(_, _, visibility, ..)| visibility.get())
+        .map(|(entity, transform, ..)| (entity, transform.translation()))
+        .collect();
+    // UI coordinates grow downwards, so this sorts top to bottom, then left to right
+    visible.sort_by(|(_, a), (_, b)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+    let current = focus
+        .0
+        .and_then(|focused| visible.iter().position(|(entity, _)| *entity == focused));
+
+    if step != 0 && !visible.is_empty() {
+        let next = match current {
+            Some(index) => (index as i32 + step).rem_euclid(visible.len() as i32) as usize,
+            None => 0,
+        };
+        let next = visible[next].0;
+        if let Some(previous) = focus.0 {
+            if let Ok((_, _, _, _, mut color, colors)) = buttons.get_mut(previous) {
+                *color = colors.normal.into();
+            }
+        }
+        if let Ok((_, _, _, _, mut color, colors)) = buttons.get_mut(next) {
+            *color = colors.hovered.into();
+        }
+        focus.0 = Some(next);
+        return;
+    }
+
+    if gamepad_just_pressed(GamepadButtonType::South) && current.is_some() {
+        if let Some(focused) = focus.0 {
+            if let Ok((_, _, _, mut interaction, _, _)) = buttons.get_mut(focused) {
+                *interaction = Interaction::Pressed;
+                gamepad.pressed = Some(focused);
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::actions::{
+    emit_action_events, ActionBuffer, ActionEvent, ActionEventKind, Actions, GameControl,
+};
+use crate::menu::controls::Rebinding;
+use crate::menu::navigation::navigate_with_gamepad;
+use crate::menu::{cleanup_screen, spawn_button, ChangeScreen, ChangeState, MenuScreen};
+use crate::replay::is_replaying;
+use crate::{GameState, PauseState};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::RapierConfiguration;
+
+pub struct PauseMenuPlugin;
+
+/// This plugin pauses the game on `GameControl::Pause` and draws the pause menu on top of the frozen game
+/// Resuming continues right where the game was paused, without entering `GameState::Playing` again
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                // before the controls screen, which may take the press as a new binding
+                // replays leave out the paused frames, so they are never paused themselves
+                toggle_pause
+                    .after(emit_action_events)
+                    .before(navigate_with_gamepad)
+                    .run_if(in_state(GameState::Playing).and_then(not(is_replaying))),
+                click_resume_button
+                    .after(navigate_with_gamepad)
+                    .run_if(in_state(MenuScreen::Pause)),
+            ),
+        )
+        .add_systems(OnEnter(PauseState::Paused), pause_game)
+        .add_systems(OnExit(PauseState::Paused), resume_game)
+        .add_systems(OnEnter(MenuScreen::Pause), setup_pause_screen)
+        .add_systems(OnExit(MenuScreen::Pause), cleanup_screen::<PauseScreen>)
+        .add_systems(OnExit(GameState::Playing), unpause);
+    }
+}
+
+#[derive(Component)]
+struct PauseScreen;
+
+#[derive(Component)]
+struct Resume;
+
+fn toggle_pause(
+    mut action_events: EventReader<ActionEvent>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    screen: Res<State<MenuScreen>>,
+    mut next_screen: ResMut<NextState<MenuScreen>>,
+    rebinding: Res<Rebinding>,
+) {
+    let mut pressed = false;
+    for event in action_events.read() {
+        if event.control == GameControl::Pause && event.kind == ActionEventKind::Pressed {
+            pressed = true;
+        }
+    }
+    if !pressed || rebinding.is_active() {
+        return;
+    }
+    match (pause_state.get(), screen.get()) {
+        (PauseState::Running, _) => next_pause_state.set(PauseState::Paused),
+        (PauseState::Paused, MenuScreen::Pause) => next_pause_state.set(PauseState::Running),
+        // screens opened from the pause menu go back to it first
+        (PauseState::Paused, _) => next_screen.set(MenuScreen::Pause),
+    }
+}
+
+fn pause_game(
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut actions: ResMut<Actions>,
+    mut action_buffer: ResMut<ActionBuffer>,
+    mut next_screen: ResMut<NextState<MenuScreen>>,
+) {
+    time.pause();
+    rapier_config.physics_pipeline_active = false;
+    // input from before the pause must not carry over, the input systems do not run while paused
+    *actions = Actions::default();
+    action_buffer.clear();
+    next_screen.set(MenuScreen::Pause);
+}
+
+fn resume_game(
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut action_buffer: ResMut<ActionBuffer>,
+    game_state: Res<State<GameState>>,
+    mut next_screen: ResMut<NextState<MenuScreen>>,
+) {
+    time.unpause();
+    rapier_config.physics_pipeline_active = true;
+    // presses in the pause menu never expire, virtual time stands still while paused
+    action_buffer.clear();
+    // quitting to the menu has already opened the main screen
+    if *game_state.get() != GameState::Menu {
+        next_screen.set(MenuScreen::Closed);
+    }
+}
+
+fn unpause(mut next_pause_state: ResMut<NextState<PauseState>>) {
+    next_pause_state.set(PauseState::Running);
+}
+
+fn setup_pause_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.),
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                ..default()
+            },
+            PauseScreen,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Paused",
+                TextStyle {
+                    font_size: 50.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+            spawn_button(children, "Resume", Resume);
+            spawn_button(children, "Restart", ChangeState(GameState::Restarting));
+            spawn_button(children, "Settings", ChangeScreen(MenuScreen::Settings));
+            spawn_button(children, "Quit to Menu", ChangeState(GameState::Menu));
+        });
+}
+
+fn click_resume_button(
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<Resume>)>,
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            next_pause_state.set(PauseState::Running);
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use crate::audio::AudioChannelKind;
+use crate::menu::navigation::navigate_with_gamepad;
+use crate::menu::{cleanup_screen, spawn_button, Back, ButtonColors, ChangeScreen, MenuScreen};
+use crate::settings::{GameSettings, MIN_RESOLUTION_SCALE};
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+
+pub struct SettingsMenuPlugin;
+
+/// This plugin draws the settings screen of the menu
+/// Every change is written to the [`GameSettings`] right away, the `SettingsPlugin` applies and saves it
+impl Plugin for SettingsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(MenuScreen::Settings), setup_settings_screen)
+            .add_systems(
+                Update,
+                (click_setting_buttons, update_setting_labels)
+                    .chain()
+                    .after(navigate_with_gamepad)
+                    .run_if(in_state(MenuScreen::Settings)),
+            )
+            .add_systems(
+                OnExit(MenuScreen::Settings),
+                cleanup_screen::<SettingsScreen>,
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Setting {
+    Msaa,
+    Vsync,
+    WindowMode,
+    ResolutionScale,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    AmbienceVolume,
+    UiVolume,
+    Mute(AudioChannelKind),
+    CameraSensitivity,
+    InvertCameraY,
+}
+
+const MSAA_SAMPLES: [u32; 4] = [1, 2, 4, 8];
+const WINDOW_MODES: [WindowMode; 3] = [
+    WindowMode::Windowed,
+    WindowMode::BorderlessFullscreen,
+    WindowMode::Fullscreen,
+];
+
+impl Setting {
+    fn label(self) -> &'static str {
+        match self {
+            Setting::Msaa => "Anti-aliasing",
+            Setting::Vsync => "VSync",
+            Setting::WindowMode => "Window mode",
+            Setting::ResolutionScale => "Resolution scale",
+            Setting::MasterVolume => "Master volume",
+            Setting::MusicVolume => "Music volume",
+            Setting::SfxVolume => "Effects volume",
+            Setting::AmbienceVolume => "Ambience volume",
+            Setting::UiVolume => "Menu volume",
+            Setting::Mute(_) => "Mute",
+            Setting::CameraSensitivity => "Camera sensitivity",
+            Setting::InvertCameraY => "Invert camera Y",
+        }
+    }
+
+    // moves the setting one step up or down, lists wrap around while ranges stop at their ends
+    fn step(self, settings: &mut GameSettings, direction: i32) {
+        match self {
+            Setting::Msaa => {
+                settings.msaa_samples = cycle(&MSAA_SAMPLES, settings.msaa_samples, direction)
+            }
+            Setting::Vsync => settings.vsync = !settings.vsync,
+            Setting::WindowMode => {
+                settings.window_mode = cycle(&WINDOW_MODES, settings.window_mode, direction)
+            }
+            Setting::ResolutionScale => {
+                settings.resolution_scale = step_range(
+                    settings.resolution_scale,
+                    direction,
+                    0.1,
+                    MIN_RESOLUTION_SCALE,
+                    1.,
+                )
+            }
+            Setting::MasterVolume => {
+                settings.master_volume = step_range(settings.master_volume, direction, 0.1, 0., 1.)
+            }
+            Setting::MusicVolume => {
+                settings.music_volume = step_range(settings.music_volume, direction, 0.1, 0., 1.)
+            }
+            Setting::SfxVolume => {
+                settings.sfx_volume = step_range(settings.sfx_volume, direction, 0.1, 0., 1.)
+            }
+            Setting::AmbienceVolume => {
+                settings.ambience_volume =
+                    step_range(settings.ambience_volume, direction, 0.1, 0., 1.)
+            }
+            Setting::UiVolume => {
+                settings.ui_volume = step_range(settings.ui_volume, direction, 0.1, 0., 1.)
+            }
+            Setting::Mute(channel) => settings.set_muted(channel, !settings.is_muted(channel)),
+            Setting::CameraSensitivity => {
+                settings.camera_sensitivity =
+                    step_range(settings.camera_sensitivity, direction, 0.25, 0.25, 3.)
+            }
+            Setting::InvertCameraY => settings.invert_camera_y = !settings.invert_camera_y,
+        }
+    }
+
+    // the channel a volume setting belongs to, which gets a mute button next to it
+    fn channel(self) -> Option<AudioChannelKind> {
+        match self {
+            Setting::MusicVolume => Some(AudioChannelKind::Music),
+            Setting::SfxVolume => Some(AudioChannelKind::Sfx),
+            Setting::AmbienceVolume => Some(AudioChannelKind::Ambience),
+            Setting::UiVolume => Some(AudioChannelKind::Ui),
+            _ => None,
+        }
+    }
+
+    fn describe(self, settings: &GameSettings) -> String {
+        let on_off = |value: bool| String::from(if value { "On" } else { "Off" });
+        let percent = |value: f32| format!("{:.0}%", value * 100.);
+        match self {
+            Setting::Msaa if settings.msaa_samples <= 1 => "Off".to_string(),
+            Setting::Msaa => format!("{}x MSAA", settings.msaa_samples),
+            Setting::Vsync => on_off(settings.vsync),
+            Setting::WindowMode => match settings.window_mode {
+                WindowMode::Windowed => "Windowed",
+                WindowMode::BorderlessFullscreen => "Borderless",
+                WindowMode::SizedFullscreen | WindowMode::Fullscreen => "Fullscreen",
+            }
+            .to_string(),
+            Setting::ResolutionScale => percent(settings.resolution_scale),
+            Setting::MasterVolume => percent(settings.master_volume),
+            Setting::MusicVolume => percent(settings.music_volume),
+            Setting::SfxVolume => percent(settings.sfx_volume),
+            Setting::AmbienceVolume => percent(settings.ambience_volume),
+            Setting::UiVolume => percent(settings.ui_volume),
+            Setting::Mute(channel) if settings.is_muted(channel) => "Muted".to_string(),
+            Setting::Mute(_) => "Mute".to_string(),
+            Setting::CameraSensitivity => format!("{:.2}x", settings.camera_sensitivity),
+            Setting::InvertCameraY => on_off(settings.invert_camera_y),
+        }
+    }
+}
+
+fn cycle<T: Copy + PartialEq>(values: &[T], current: T, direction: i32) -> T {
+    let index = values
+        .iter()
+        .position(|value| *value == current)
+        .unwrap_or(0) as i32;
+    values[(index + direction).rem_euclid(values.len() as i32) as usize]
+}
+
+fn step_range(value: f32, direction: i32, step: f32, min: f32, max: f32) -> f32 {
+    // snapping to the grid keeps hand-edited values from drifting
+    (((value / step).round() + direction as f32) * step).clamp(min, max)
+}
+
+#[derive(Component)]
+struct SettingsScreen;
+
+// Decreases or increases a setting when pressed
+#[derive(Component)]
+struct SettingButton {
+    setting: Setting,
+    direction: i32,
+}
+
+impl SettingButton {
+    fn new(setting: Setting, direction: i32) -> Self {
+        SettingButton { setting, direction }
+    }
+}
+
+#[derive(Component)]
+struct SettingLabel(Setting);
+
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+fn setup_settings_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(6.),
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                ..default()
+            },
+            SettingsScreen,
+        ))
+        .with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Settings",
+                TextStyle {
+                    font_size: 40.0,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+            for (heading, settings) in [
+                (
+                    "Graphics",
+                    &[
+                        Setting::Msaa,
+                        Setting::Vsync,
+                        Setting::WindowMode,
+                        Setting::ResolutionScale,
+                    ][..],
+                ),
+                (
+                    "Audio",
+                    &[
+                        Setting::MasterVolume,
+                        Setting::MusicVolume,
+                        Setting::SfxVolume,
+                        Setting::AmbienceVolume,
+                        Setting::UiVolume,
+                    ][..],
+                ),
+                (
+                    "Gameplay",
+                    &[Setting::CameraSensitivity, Setting::InvertCameraY][..],
+                ),
+            ] {
+                children.spawn(
+                    TextBundle::from_section(
+                        heading,
+                        TextStyle {
+                            font_size: 28.0,
+                            color: TEXT_COLOR,
+                            ..default()
+                        },
+                    )
+                    .with_style(Style {
+                        margin: UiRect::top(Val::Px(10.)),
+                        ..default()
+                    }),
+                );
+                for setting in settings {
+                    spawn_setting_row(children, *setting);
+                }
+            }
+            children
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(10.),
+                        margin: UiRect::top(Val::Px(20.)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_button(parent, "Controls", ChangeScreen(MenuScreen::Controls));
+                    spawn_button(parent, "Back", Back);
+                });
+        });
+}
+
+fn spawn_setting_row(children: &mut ChildBuilder, setting: Setting) {
+    children
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    setting.label(),
+                    TextStyle {
+                        font_size: 22.0,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    width: Val::Px(200.0),
+                    ..default()
+                }),
+            );
+            spawn_setting_button(
+                parent,
+                36.0,
+                SettingButton::new(setting, -1),
+                button_text("<"),
+            );
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 22.0,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    width: Val::Px(140.0),
+                    ..default()
+                })
+                .with_text_alignment(TextAlignment::Center),
+                SettingLabel(setting),
+            ));
+            spawn_setting_button(
+                parent,
+                36.0,
+                SettingButton::new(setting, 1),
+                button_text(">"),
+            );
+            if let Some(channel) = setting.channel() {
+                let mute = Setting::Mute(channel);
+                spawn_setting_button(
+                    parent,
+                    90.0,
+                    SettingButton::new(mute, 1),
+                    (button_text(""), SettingLabel(mute)),
+                );
+            }
+        });
+}
+
+fn spawn_setting_button(
+    parent: &mut ChildBuilder,
+    width: f32,
+    button: SettingButton,
+    label: impl Bundle,
+) {
+    let button_colors = ButtonColors::default();
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Px(32.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: button_colors.normal.into(),
+                ..default()
+            },
+            button_colors,
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(label);
+        });
+}
+
+fn button_text(value: &str) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font_size: 22.0,
+            color: TEXT_COLOR,
+            ..default()
+        },
+    )
+}
+
+fn click_setting_buttons(
+    mut settings: ResMut<GameSettings>,
+    interaction_query: Query<(&Interaction, &SettingButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            let mut changed = settings.clone();
+            button.setting.step(&mut changed, button.direction);
+            // only real changes are applied and saved
+            settings.set_if_neq(changed);
+        }
+    }
+}
+
+fn update_setting_labels(
+    settings: Res<GameSettings>,
+    mut labels: Query<(&SettingLabel, &mut Text)>,
+    added: Query<(), Added<SettingsScreen>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (label, mut text) in &mut labels {
+        text.sections[0].value = label.0.describe(&settings);
+    }
+}
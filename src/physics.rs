@@ -0,0 +1,71 @@
+use crate::actions::{emit_action_events, ActionEvent, ActionEventKind, GameControl};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::render::RenderPlugin;
+use bevy_rapier3d::prelude::*;
+
+/// Standard gravity in meters per second squared
+pub const GRAVITY: f32 = 9.81;
+
+/// This plugin registers the Rapier physics simulation and its debug renderer
+/// The simulation is advanced with a fixed timestep, independent of the frame rate
+pub struct PhysicsPlugin {
+    /// How many world units make up one meter
+    pub units_per_meter: f32,
+    /// Length of a single physics step in seconds
+    pub timestep: f32,
+    /// Whether the collider wireframes are drawn on start (toggled with `GameControl::DebugRender` while playing)
+    pub debug_render: bool,
+}
+
+impl Default for PhysicsPlugin {
+    fn default() -> Self {
+        PhysicsPlugin {
+            units_per_meter: 1.0,
+            timestep: 1. / 60.,
+            debug_render: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(
+            RapierPhysicsPlugin::<NoUserData>::default().with_physics_scale(self.units_per_meter),
+        )
+        .insert_resource(RapierConfiguration {
+            gravity: Vec3::NEG_Y * GRAVITY * self.units_per_meter,
+            timestep_mode: TimestepMode::Interpolated {
+                dt: self.timestep,
+                time_scale: 1.0,
+                substeps: 1,
+            },
+            ..default()
+        });
+
+        // headless apps, like the replay runner, have nothing to draw the debug renderer with
+        if app.is_plugin_added::<RenderPlugin>() {
+            app.add_plugins(RapierDebugRenderPlugin {
+                enabled: self.debug_render,
+                ..default()
+            })
+            .add_systems(
+                Update,
+                toggle_debug_render
+                    .after(emit_action_events)
+                    .run_if(in_state(GameState::Playing)),
+            );
+        }
+    }
+}
+
+fn toggle_debug_render(
+    mut action_events: EventReader<ActionEvent>,
+    mut debug_render: ResMut<DebugRenderContext>,
+) {
+    for event in action_events.read() {
+        if event.control == GameControl::DebugRender && event.kind == ActionEventKind::Pressed {
+            debug_render.enabled = !debug_render.enabled;
+        }
+    }
+}
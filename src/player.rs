@@ -1,12 +1,15 @@
-use crate::actions::Actions;
-use crate::GameState;
-use bevy::{prelude::*, render::mesh::VertexAttributeValues};
-use bevy_rapier3d::{
-    prelude::{
-        CharacterAutostep, CharacterLength, Collider, KinematicCharacterController, NoUserData,
-        QueryFilter, RapierConfiguration, RapierContext, RapierPhysicsPlugin, RigidBody,
-    },
-    render::RapierDebugRenderPlugin,
+use crate::actions::{
+    emit_action_events, set_camera_action, set_gait_action, set_movement_actions, ActionBuffer,
+    Actions, Gait, GameControl,
+};
+use crate::loading::StageAssets;
+use crate::stage::{CurrentStage, StageDefinition};
+use crate::{DespawnOnExit, GameState, PauseState};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{
+    ActiveCollisionTypes, ActiveEvents, CharacterAutostep, CharacterLength, Collider,
+    KinematicCharacterController, KinematicCharacterControllerOutput, QueryFilter,
+    QueryFilterFlags, RapierConfiguration, RapierContext, RigidBody,
 };
 use bevy_third_person_camera::*;
 
@@ -15,134 +18,381 @@ pub struct PlayerPlugin;
 #[derive(Component)]
 pub struct Player;
 
+/// Tunable movement parameters of the player, applied to its [`KinematicCharacterController`]
+#[derive(Component)]
+pub struct PlayerMovementConfig {
+    /// Top horizontal running speed in meters per second
+    pub speed: f32,
+    /// Factor applied to `speed` while sprinting
+    pub sprint_multiplier: f32,
+    /// Factor applied to `speed` while walking
+    pub walk_multiplier: f32,
+    /// How quickly the top speed is reached (and lost), in meters per second squared
+    pub acceleration: f32,
+    /// Steepest slope the player can walk up, in degrees
+    pub max_slope: f32,
+    /// Highest step that is climbed without jumping, in meters
+    pub autostep_height: f32,
+    /// Distance in meters the player is pulled down to stay on slopes and stairs
+    pub snap_to_ground: f32,
+    /// Highest falling speed in meters per second
+    pub terminal_velocity: f32,
+    /// Height of a jump in meters
+    pub jump_height: f32,
+    /// Number of extra jumps in mid air (1 for a double jump)
+    pub air_jumps: u32,
+    /// Seconds after walking off a ledge during which the player can still jump from the ground
+    pub coyote_time: f32,
+}
+
+impl Default for PlayerMovementConfig {
+    fn default() -> Self {
+        PlayerMovementConfig {
+            speed: 4.0,
+            sprint_multiplier: 1.75,
+            walk_multiplier: 0.4,
+            acceleration: 30.0,
+            max_slope: 45.0,
+            autostep_height: 0.3,
+            snap_to_ground: 0.2,
+            terminal_velocity: 50.0,
+            jump_height: 1.2,
+            air_jumps: 1,
+            coyote_time: 0.1,
+        }
+    }
+}
+
+/// Current velocity of the player
+#[derive(Component, Default)]
+pub struct PlayerMotion {
+    pub velocity: Vec3,
+}
+
+#[derive(Component, Default)]
+struct JumpState {
+    // remaining time in which a ground jump is allowed
+    coyote: f32,
+    air_jumps_used: u32,
+}
+
+/// Whether the player is standing on something, determined by a shape cast below its feet
+/// Gravity only pulls on the player while it is [`GroundState::Airborne`]
+#[derive(Component, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum GroundState {
+    #[default]
+    Grounded,
+    Airborne,
+}
+
+/// Sent when the player jumps, from the ground or in mid air
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlayerJumped;
+
+/// Sent when the player touches the ground after being [`GroundState::Airborne`]
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlayerLanded {
+    /// Falling speed in meters per second just before the landing
+    pub speed: f32,
+}
+
+/// The collider below the player's feet while it is [`GroundState::Grounded`]
+#[derive(Component, Default)]
+pub struct GroundContact(pub Option<Entity>);
+
+// the capsule is shifted up so that the player's origin stays at its feet, like the model's
+const PLAYER_HEIGHT: f32 = 1.0;
+const PLAYER_RADIUS: f32 = 0.25;
+// how far below the feet something still counts as ground
+const GROUND_CHECK_DISTANCE: f32 = 0.05;
+// steepest angle in radians the gamepad can tilt the camera above or below the player
+const MAX_CAMERA_PITCH: f32 = 1.4;
+
 /// This plugin handles player related stuff like movement
 /// Player logic is only active during the State `GameState::Playing`
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app /*.add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
-            .add_plugins(RapierDebugRenderPlugin::default())*/
-            .add_plugins(ThirdPersonCameraPlugin)
-            .init_resource::<RapierContext>()
-            .insert_resource(RapierConfiguration {
-                gravity: Vec3::Y * -980.0,
-                ..default()
-            })
+        app.add_event::<PlayerJumped>()
+            .add_event::<PlayerLanded>()
             .add_systems(OnEnter(GameState::Playing), spawn_player)
             .add_systems(
                 Update,
                 (
-                    player_movement_keyboard,
-                    check_player_collisions, /*, update_gravity*/
+                    apply_movement_config,
+                    (
+                        update_player_motion,
+                        update_ground_state,
+                        player_jump.after(emit_action_events),
+                        apply_gravity,
+                        move_player
+                            .after(set_movement_actions)
+                            .after(set_gait_action),
+                    )
+                        .chain()
+                        .after(apply_movement_config),
+                    orbit_camera.after(set_camera_action),
                 )
-                    .run_if(in_state(GameState::Playing)),
+                    .run_if(in_state(GameState::Playing).and_then(in_state(PauseState::Running))),
             );
     }
 }
 
-fn spawn_player(mut commands: Commands, assets: Res<AssetServer>) {
+fn spawn_player(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    stages: Res<StageAssets>,
+    current_stage: Res<CurrentStage>,
+    definitions: Res<Assets<StageDefinition>>,
+) {
+    let spawn_point = current_stage
+        .definition(&stages, &definitions)
+        .map_or(Vec3::ZERO, StageDefinition::spawn_point);
     commands.spawn((
         SceneBundle {
             scene: assets.load("models/Player.gltf#Scene0"),
-            transform: Transform::from_xyz(0., 0., 0.),
+            transform: Transform::from_translation(spawn_point),
             ..Default::default()
         },
-        // RigidBody::KinematicPositionBased,
-        RigidBody::Dynamic,
-        Collider::cylinder(1.0, 0.5),
-        // KinematicCharacterController {
-        //     autostep: Some(CharacterAutostep {
-        //         max_height: CharacterLength::Relative(0.3),
-        //         min_width: CharacterLength::Relative(0.5),
-        //         include_dynamic_bodies: false,
-        //     }),
-        //     // snap_to_ground: Some(CharacterAutostep {}),
-        //     ..default()
-        // },
+        RigidBody::KinematicPositionBased,
+        player_collider(),
+        KinematicCharacterController {
+            custom_shape: Some((
+                Collider::capsule_y(PLAYER_HEIGHT / 2. - PLAYER_RADIUS, PLAYER_RADIUS),
+                Vec3::Y * PLAYER_HEIGHT / 2.,
+                Quat::IDENTITY,
+            )),
+            // sensors are walked through, they only report collisions
+            filter_flags: QueryFilterFlags::EXCLUDE_SENSORS,
+            ..default()
+        },
+        // the kinematic player has to opt in to collision events with the fixed stage
+        ActiveEvents::COLLISION_EVENTS,
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        PlayerMovementConfig::default(),
+        PlayerMotion::default(),
+        GroundState::default(),
+        GroundContact::default(),
+        JumpState::default(),
         Player,
         ThirdPersonCameraTarget,
+        DespawnOnExit(GameState::Playing),
     ));
 }
 
-fn update_gravity(
-    mut gravity_affected: Query<(&mut Transform, &RigidBody), With<Collider>>,
+fn player_collider() -> Collider {
+    Collider::compound(vec![(
+        Vec3::Y * PLAYER_HEIGHT / 2.,
+        Quat::IDENTITY,
+        Collider::capsule_y(PLAYER_HEIGHT / 2. - PLAYER_RADIUS, PLAYER_RADIUS),
+    )])
+}
+
+fn apply_movement_config(
+    mut player_q: Query<
+        (&PlayerMovementConfig, &mut KinematicCharacterController),
+        Changed<PlayerMovementConfig>,
+    >,
+) {
+    for (config, mut controller) in player_q.iter_mut() {
+        controller.max_slope_climb_angle = config.max_slope.to_radians();
+        // anything that can not be climbed makes the player slide down
+        controller.min_slope_slide_angle = config.max_slope.to_radians();
+        controller.autostep = (config.autostep_height > 0.).then_some(CharacterAutostep {
+            max_height: CharacterLength::Absolute(config.autostep_height),
+            min_width: CharacterLength::Relative(0.5),
+            include_dynamic_bodies: false,
+        });
+        controller.snap_to_ground = (config.snap_to_ground > 0.)
+            .then_some(CharacterLength::Absolute(config.snap_to_ground));
+    }
+}
+
+fn update_player_motion(
     time: Res<Time>,
+    mut player_q: Query<(&KinematicCharacterControllerOutput, &mut PlayerMotion)>,
 ) {
-    // TODO:
-    // This also is pushing below the floor which is not ok.
-    // for each item that can have gravity, pull it down to the floor
-    let gravity = -1.;
-    for mut each in gravity_affected.iter_mut() {
-        if each.1 != &RigidBody::Fixed {
-            // adding the delta helped, but it needs the flooring to work still.
-            each.0.translation.y += gravity * time.delta_seconds();
+    if time.delta_seconds() == 0. {
+        return;
+    }
+    for (output, mut motion) in player_q.iter_mut() {
+        // don't keep pushing into whatever stopped the last move
+        let effective = output.effective_translation / time.delta_seconds();
+        motion.velocity.x = effective.x;
+        motion.velocity.z = effective.z;
+        if motion.velocity.y > 0. {
+            // bumped into a ceiling
+            motion.velocity.y = motion.velocity.y.min(effective.y);
         }
     }
 }
 
-fn check_player_collisions(
-    mut commands: Commands,
+fn update_ground_state(
     rapier_context: Res<RapierContext>,
-    mut player_query: Query<(&Collider, &mut Transform), With<Player>>,
-    object_query: Query<&Collider, Without<Player>>,
+    mut landed: EventWriter<PlayerLanded>,
+    mut player_q: Query<
+        (
+            Entity,
+            &Transform,
+            &PlayerMotion,
+            &mut GroundState,
+            &mut GroundContact,
+        ),
+        With<Player>,
+    >,
 ) {
-    let (player_collider, player_transform) = player_query.single_mut();
-    // for object in object_query.iter() {
-    match rapier_context.intersection_with_shape(
-        player_transform.translation,
-        player_transform.rotation,
-        player_collider,
-        QueryFilter::default(),
-    ) {
-        Some(entity) => {
-            let thing = commands.entity(entity);
+    // slightly thinner than the player so walls next to it do not count as ground
+    let feet = Collider::ball(PLAYER_RADIUS * 0.9);
+    for (entity, transform, motion, mut ground_state, mut contact) in player_q.iter_mut() {
+        let hit = rapier_context.cast_shape(
+            transform.translation + Vec3::Y * PLAYER_RADIUS,
+            Quat::IDENTITY,
+            Vec3::NEG_Y,
+            &feet,
+            PLAYER_RADIUS * 0.1 + GROUND_CHECK_DISTANCE,
+            // feet that already sink into the ground are standing on it
+            true,
+            QueryFilter::default()
+                .exclude_collider(entity)
+                .exclude_sensors(),
+        );
+        let new_state = if hit.is_some() {
+            GroundState::Grounded
+        } else {
+            GroundState::Airborne
+        };
+        if *ground_state == GroundState::Airborne && new_state == GroundState::Grounded {
+            landed.send(PlayerLanded {
+                speed: (-motion.velocity.y).max(0.),
+            });
         }
-        None => {}
+        ground_state.set_if_neq(new_state);
+        contact.0 = hit.map(|(ground, _)| ground);
     }
-    // }
 }
 
-fn player_movement_keyboard(
+fn player_jump(
     time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
-    mut player_q: Query<&mut Transform, With<Player>>,
-    cam_q: Query<&Transform, (With<ThirdPersonCamera>, Without<Player>)>,
+    mut action_buffer: ResMut<ActionBuffer>,
+    rapier_config: Res<RapierConfiguration>,
+    mut jumped: EventWriter<PlayerJumped>,
+    mut player_q: Query<(
+        &GroundState,
+        &mut PlayerMotion,
+        &mut JumpState,
+        &PlayerMovementConfig,
+    )>,
 ) {
-    for mut player_transform in player_q.iter_mut() {
-        let cam = match cam_q.get_single() {
-            Ok(c) => c,
-            Err(e) => Err(format!("Error retrieving camera: {}", e)).unwrap(),
-        };
-
-        let mut direction = Vec2::ZERO;
-
-        // forward
-        if keys.pressed(KeyCode::W) {
-            direction += cam.forward().xz().normalize();
+    let delta = time.delta_seconds();
+    for (ground_state, mut motion, mut jump, config) in player_q.iter_mut() {
+        if *ground_state == GroundState::Grounded && motion.velocity.y <= 0. {
+            jump.coyote = config.coyote_time;
+            jump.air_jumps_used = 0;
+        } else {
+            jump.coyote = (jump.coyote - delta).max(0.);
         }
 
-        // back
-        if keys.pressed(KeyCode::R) {
-            direction += cam.back().xz().normalize();
+        // without a jump available the press stays in the buffer, e.g. until the player lands
+        let from_ground = jump.coyote > 0.;
+        if !from_ground && jump.air_jumps_used >= config.air_jumps {
+            continue;
         }
-
-        // left
-        if keys.pressed(KeyCode::A) {
-            direction += cam.left().xz().normalize();
+        if !action_buffer.consume(GameControl::Jump) {
+            continue;
+        }
+        if from_ground {
+            jump.coyote = 0.;
+        } else {
+            jump.air_jumps_used += 1;
         }
+        // initial speed needed to reach the jump height against gravity
+        motion.velocity.y = (2. * rapier_config.gravity.length() * config.jump_height).sqrt();
+        jumped.send(PlayerJumped);
+    }
+}
 
-        // right
-        if keys.pressed(KeyCode::S) {
-            direction += cam.right().xz().normalize();
+fn apply_gravity(
+    time: Res<Time>,
+    rapier_config: Res<RapierConfiguration>,
+    mut player_q: Query<(&GroundState, &mut PlayerMotion, &PlayerMovementConfig)>,
+) {
+    for (ground_state, mut motion, config) in player_q.iter_mut() {
+        match ground_state {
+            GroundState::Airborne => {
+                motion.velocity += rapier_config.gravity * time.delta_seconds();
+                motion.velocity.y = motion.velocity.y.max(-config.terminal_velocity);
+            }
+            GroundState::Grounded => {
+                motion.velocity.y = motion.velocity.y.max(0.);
+            }
         }
+    }
+}
 
-        let movement = direction * time.delta_seconds();
-        player_transform.translation.x += movement.x;
-        player_transform.translation.z += movement.y;
-        let direction: Vec3 = (direction.x, 0.0, direction.y).into();
+pub fn move_player(
+    time: Res<Time>,
+    actions: Res<Actions>,
+    mut player_q: Query<
+        (
+            &mut Transform,
+            &mut KinematicCharacterController,
+            &mut PlayerMotion,
+            &PlayerMovementConfig,
+        ),
+        With<Player>,
+    >,
+) {
+    let direction = actions.player_movement.unwrap_or(Vec2::ZERO);
+    for (mut player_transform, mut controller, mut motion, config) in player_q.iter_mut() {
+        let speed = config.speed
+            * match actions.gait {
+                Gait::Walk => config.walk_multiplier,
+                Gait::Run => 1.,
+                Gait::Sprint => config.sprint_multiplier,
+            };
+        let velocity = move_towards(
+            motion.velocity.xz(),
+            direction * speed,
+            config.acceleration * time.delta_seconds(),
+        );
+        motion.velocity.x = velocity.x;
+        motion.velocity.z = velocity.y;
+        controller.translation = Some(motion.velocity * time.delta_seconds());
 
         // rotate player to face direction he is currently moving
-        if direction.length_squared() > 0.0 {
-            player_transform.look_to(direction, Vec3::Y);
+        if direction != Vec2::ZERO {
+            player_transform.look_to(Vec3::new(direction.x, 0.0, direction.y), Vec3::Y);
         }
     }
 }
+
+fn move_towards(current: Vec2, target: Vec2, max_delta: f32) -> Vec2 {
+    let delta = target - current;
+    if delta.length() <= max_delta {
+        target
+    } else {
+        current + delta.normalize() * max_delta
+    }
+}
+
+// the camera plugin does not orbit with the mouse, so every device rotates the camera around its focus here
+fn orbit_camera(actions: Res<Actions>, mut camera_q: Query<(&ThirdPersonCamera, &mut Transform)>) {
+    if actions.camera_rotation == Vec2::ZERO {
+        return;
+    }
+    let Ok((camera, mut transform)) = camera_q.get_single_mut() else {
+        return;
+    };
+    let rotation = actions.camera_rotation;
+    let offset = transform.translation - camera.true_focus;
+
+    let yaw = Quat::from_rotation_y(-rotation.x);
+    let pitch = Quat::from_axis_angle(transform.right(), rotation.y);
+    let pitched = (pitch * offset).normalize_or_zero();
+    let orbit = if pitched.y.abs() < MAX_CAMERA_PITCH.sin() {
+        yaw * pitch
+    } else {
+        yaw
+    };
+    transform.translation = camera.true_focus + orbit * offset;
+    transform.rotation = orbit * transform.rotation;
+}
@@ -0,0 +1,618 @@
+use crate::actions::{
+    set_camera_action, set_gait_action, set_movement_actions, ActionBuffer, Actions, ActionsPlugin,
+    GamepadConfig, KeyBindings,
+};
+use crate::collision::CollisionPlugin;
+use crate::loading::StageAssets;
+use crate::physics::PhysicsPlugin;
+use crate::player::{Player, PlayerPlugin};
+use crate::stage::{CurrentStage, StagePlugin};
+use crate::{headless_app, GameState, PauseState};
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::input::gamepad::{
+    gamepad_event_system, GamepadConnection, GamepadConnectionEvent, GamepadEvent, GamepadInfo,
+};
+use bevy::input::keyboard::{keyboard_input_system, KeyboardInput};
+use bevy::input::mouse::{mouse_button_input_system, MouseButtonInput, MouseMotion};
+use bevy::input::touch::{touch_screen_input_system, TouchInput};
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_asset_loader::prelude::*;
+use bevy_third_person_camera::ThirdPersonCamera;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use thiserror::Error;
+
+const REPLAY_VERSION: u32 = 3;
+// one frame at 60 fps, the rate of the physics steps
+const REPLAY_TIMESTEP: Duration = Duration::from_nanos(16_666_667);
+// frames a headless replay may spend loading before it is given up
+const HEADLESS_LOADING_FRAMES: usize = 100_000;
+// the player may end up this far from the recorded position before a replay counts as out of sync
+const DESYNC_TOLERANCE: f32 = 1e-3;
+
+pub struct ReplayPlugin;
+
+/// This plugin records the raw device input of every unpaused frame in `GameState::Playing` and plays it back
+/// Start the game with `--record <file>` to record, or with `--replay <file>` to skip the menu,
+/// play the file back and exit once it is done. Adding `--headless` plays it back without a window,
+/// see [`run_headless`].
+/// Played back input goes through the key bindings of the recording, so a replay also covers the way
+/// input turns into [`Actions`]. The recorded `Actions` of every frame are compared to the replayed ones.
+/// Both run on a fixed timestep: every frame advances the game clock by exactly one step, so the result
+/// does not depend on the frame rate of the recording or the replaying machine. This also holds for the
+/// game while it is recorded, which runs slower or faster than real time when it does not reach 60 fps.
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        // headless replays set up their state before adding the plugin
+        let state = app
+            .world
+            .remove_resource::<ReplayState>()
+            .unwrap_or_else(|| ReplayState::from_args(std::env::args().skip(1)));
+        match &state {
+            ReplayState::Recording { replay, .. } => {
+                app.insert_resource(TimeUpdateStrategy::ManualDuration(replay.timestep));
+            }
+            ReplayState::Replaying { replay, .. } => {
+                // inserted while building, so they are not saved over the player's own configuration
+                app.insert_resource(TimeUpdateStrategy::ManualDuration(replay.timestep))
+                    .insert_resource(replay.key_bindings.clone())
+                    .insert_resource(replay.gamepad_config.clone())
+                    .insert_resource(replay.action_buffer.clone());
+            }
+            ReplayState::Off => {}
+        }
+        app.insert_resource(state)
+            .add_systems(OnEnter(GameState::Menu), start_replay)
+            .add_systems(OnEnter(GameState::Playing), start_recording)
+            .add_systems(
+                PreUpdate,
+                // after the live input was sent, which is replaced, and before it is read
+                replay_frame
+                    .in_set(InputSystem)
+                    .before(keyboard_input_system)
+                    .before(mouse_button_input_system)
+                    .before(gamepad_event_system)
+                    .before(touch_screen_input_system)
+                    .run_if(is_replaying.and_then(playing_this_frame)),
+            )
+            .add_systems(
+                Update,
+                (
+                    // runs outside of `GameState::Playing` too, so no input from before the recording is picked up
+                    record_frame,
+                    compare_actions.run_if(is_replaying),
+                )
+                    .after(set_movement_actions)
+                    .after(set_gait_action)
+                    .after(set_camera_action),
+            )
+            .add_systems(OnExit(GameState::Playing), finish_recording)
+            .add_systems(Last, finish_recording.run_if(on_event::<AppExit>()));
+    }
+}
+
+/// Everything needed to play a recorded session back
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Index of the stage the recording started on
+    pub stage: usize,
+    /// Game time that passes in every frame, also while recording
+    pub timestep: Duration,
+    pub key_bindings: KeyBindings,
+    pub gamepad_config: GamepadConfig,
+    /// Decides how early a press may come, e.g. a jump right before landing
+    pub action_buffer: ActionBuffer,
+    /// Gamepads that were already connected when the recording started
+    pub gamepads: Vec<GamepadConnectionEvent>,
+    pub frames: Vec<ReplayFrame>,
+    /// Where the player was when the recording stopped, used to detect replays going out of sync
+    pub final_position: Option<Vec3>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            stage: 0,
+            timestep: REPLAY_TIMESTEP,
+            key_bindings: KeyBindings::default(),
+            gamepad_config: GamepadConfig::default(),
+            action_buffer: ActionBuffer::default(),
+            gamepads: Vec::new(),
+            frames: Vec::new(),
+            final_position: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub input: RawInput,
+    /// Where the camera was, which decides the direction the movement input walks in
+    /// The camera plugin, which follows the player, does not run in a headless replay.
+    pub camera: Option<GlobalTransform>,
+    /// What the input turned into, to find the first frame a replay goes out of sync
+    pub actions: Actions,
+}
+
+/// The device events of a frame, before the [`KeyBindings`] apply
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RawInput {
+    pub keyboard: Vec<KeyboardInput>,
+    pub mouse_buttons: Vec<MouseButtonInput>,
+    pub mouse_motion: Vec<MouseMotion>,
+    pub gamepad: Vec<GamepadEvent>,
+    pub touches: Vec<TouchInput>,
+}
+
+/// How the end of a replay compares to the end of its recording
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ReplayOutcome {
+    pub recorded: Option<Vec3>,
+    pub replayed: Option<Vec3>,
+    /// The first frame whose [`Actions`] moved the player differently than in the recording
+    pub actions_diverged: Option<usize>,
+}
+
+impl ReplayOutcome {
+    pub fn in_sync(&self) -> bool {
+        if self.actions_diverged.is_some() {
+            return false;
+        }
+        match (self.recorded, self.replayed) {
+            (Some(recorded), Some(replayed)) => recorded.distance(replayed) <= DESYNC_TOLERANCE,
+            // nothing to compare against
+            (None, _) => true,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("could not access the replay file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not encode or decode the replay: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("replay version {0} is not supported, expected version {REPLAY_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("the replay did not finish within {0} frames")]
+    Unfinished(usize),
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let bytes = std::fs::read(path)?;
+        let replay: Replay = bincode::options().deserialize(&bytes)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(replay.version));
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        std::fs::write(path, bincode::options().serialize(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Resource, Default)]
+pub enum ReplayState {
+    #[default]
+    Off,
+    Recording {
+        path: PathBuf,
+        replay: Replay,
+        // input while paused, which is recorded with the first frame after the pause
+        paused_input: RawInput,
+    },
+    Replaying {
+        replay: Replay,
+        // index of the next frame to play
+        frame: usize,
+        actions_diverged: Option<usize>,
+    },
+}
+
+impl ReplayState {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), args.next()) {
+                ("--record", Some(path)) => {
+                    info!(
+                        "Recording to {path}, the game clock advances {REPLAY_TIMESTEP:?} every frame"
+                    );
+                    return ReplayState::Recording {
+                        path: path.into(),
+                        replay: Replay::default(),
+                        paused_input: RawInput::default(),
+                    };
+                }
+                ("--replay", Some(path)) => match Replay::load(Path::new(&path)) {
+                    Ok(replay) => {
+                        info!("Replaying {path} ({} frames)", replay.frames.len());
+                        return ReplayState::Replaying {
+                            replay,
+                            frame: 0,
+                            actions_diverged: None,
+                        };
+                    }
+                    Err(error) => error!("Failed to load replay {path}: {error}"),
+                },
+                _ => {}
+            }
+        }
+        ReplayState::Off
+    }
+}
+
+/// Run condition that is true while recorded input replaces the player's input
+pub fn is_replaying(state: Res<ReplayState>) -> bool {
+    matches!(*state, ReplayState::Replaying { .. })
+}
+
+// Whether the `Update` of this frame runs in `GameState::Playing`, which is what `record_frame` records
+// The state transition only happens after `PreUpdate`, where the input is replayed.
+fn playing_this_frame(state: Res<State<GameState>>, next_state: Res<NextState<GameState>>) -> bool {
+    match &next_state.0 {
+        Some(next) => *next == GameState::Playing,
+        None => *state.get() == GameState::Playing,
+    }
+}
+
+fn start_replay(
+    state: Res<ReplayState>,
+    mut current_stage: ResMut<CurrentStage>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if let ReplayState::Replaying {
+        replay, frame: 0, ..
+    } = &*state
+    {
+        current_stage.0 = replay.stage;
+        next_state.set(GameState::Playing);
+    }
+}
+
+// Keeps everything the recorded input depends on
+fn start_recording(
+    mut state: ResMut<ReplayState>,
+    current_stage: Res<CurrentStage>,
+    key_bindings: Res<KeyBindings>,
+    gamepad_config: Res<GamepadConfig>,
+    action_buffer: Res<ActionBuffer>,
+    gamepads: Res<Gamepads>,
+) {
+    let ReplayState::Recording { replay, .. } = &mut *state else {
+        return;
+    };
+    replay.stage = current_stage.0;
+    replay.key_bindings = key_bindings.clone();
+    replay.gamepad_config = gamepad_config.clone();
+    replay.action_buffer = action_buffer.clone();
+    replay.gamepads = gamepads
+        .iter()
+        .map(|gamepad| {
+            let name = gamepads.name(gamepad).unwrap_or_default().to_string();
+            GamepadConnectionEvent::new(gamepad, GamepadConnection::Connected(GamepadInfo { name }))
+        })
+        .collect();
+}
+
+// Reads the device events of the current frame
+#[derive(SystemParam)]
+struct RawInputReader<'w, 's> {
+    keyboard: EventReader<'w, 's, KeyboardInput>,
+    mouse_buttons: EventReader<'w, 's, MouseButtonInput>,
+    mouse_motion: EventReader<'w, 's, MouseMotion>,
+    gamepad: EventReader<'w, 's, GamepadEvent>,
+    touches: EventReader<'w, 's, TouchInput>,
+}
+
+impl RawInput {
+    fn append(&mut self, other: RawInput) {
+        self.keyboard.extend(other.keyboard);
+        self.mouse_buttons.extend(other.mouse_buttons);
+        self.mouse_motion.extend(other.mouse_motion);
+        self.gamepad.extend(other.gamepad);
+        self.touches.extend(other.touches);
+    }
+}
+
+impl RawInputReader<'_, '_> {
+    fn read(&mut self) -> RawInput {
+        RawInput {
+            keyboard: self.keyboard.read().copied().collect(),
+            mouse_buttons: self.mouse_buttons.read().copied().collect(),
+            mouse_motion: self.mouse_motion.read().cloned().collect(),
+            gamepad: self.gamepad.read().cloned().collect(),
+            touches: self.touches.read().copied().collect(),
+        }
+    }
+}
+
+// Replaces the device events of the current frame
+#[derive(SystemParam)]
+struct RawInputWriter<'w> {
+    keyboard: ResMut<'w, Events<KeyboardInput>>,
+    mouse_buttons: ResMut<'w, Events<MouseButtonInput>>,
+    mouse_motion: ResMut<'w, Events<MouseMotion>>,
+    gamepad: ResMut<'w, Events<GamepadEvent>>,
+    touches: ResMut<'w, Events<TouchInput>>,
+}
+
+impl RawInputWriter<'_> {
+    fn write(&mut self, input: &RawInput) {
+        self.keyboard.clear();
+        self.keyboard.extend(input.keyboard.iter().copied());
+        self.mouse_buttons.clear();
+        self.mouse_buttons
+            .extend(input.mouse_buttons.iter().copied());
+        self.mouse_motion.clear();
+        self.mouse_motion.extend(input.mouse_motion.iter().cloned());
+        self.gamepad.clear();
+        self.gamepad.extend(input.gamepad.iter().cloned());
+        self.touches.clear();
+        self.touches.extend(input.touches.iter().copied());
+    }
+
+    fn connect_gamepads(&mut self, gamepads: &[GamepadConnectionEvent]) {
+        self.gamepad
+            .extend(gamepads.iter().cloned().map(GamepadEvent::Connection));
+    }
+}
+
+fn record_frame(
+    mut raw_input: RawInputReader,
+    game_state: Res<State<GameState>>,
+    pause_state: Res<State<PauseState>>,
+    camera: Query<&GlobalTransform, With<ThirdPersonCamera>>,
+    actions: Res<Actions>,
+    mut state: ResMut<ReplayState>,
+) {
+    // read every frame, so the first recorded frame only holds its own events
+    let input = raw_input.read();
+    let ReplayState::Recording {
+        replay,
+        paused_input,
+        ..
+    } = &mut *state
+    else {
+        return;
+    };
+    if *game_state.get() != GameState::Playing {
+        return;
+    }
+    // the game stands still while paused, but a key let go in the pause menu is released afterwards
+    if *pause_state.get() == PauseState::Paused {
+        paused_input.append(input);
+        return;
+    }
+    let mut frame_input = std::mem::take(paused_input);
+    frame_input.append(input);
+    replay.frames.push(ReplayFrame {
+        input: frame_input,
+        camera: camera.get_single().ok().copied(),
+        actions: actions.clone(),
+    });
+}
+
+// The camera is put where it was recorded, so only the actions that move the player have to match
+fn compare_actions(actions: Res<Actions>, mut state: ResMut<ReplayState>) {
+    let ReplayState::Replaying {
+        replay,
+        frame,
+        actions_diverged,
+    } = &mut *state
+    else {
+        return;
+    };
+    // the frame played back in this update, if it was one of the recorded frames
+    let Some(recorded) = frame
+        .checked_sub(1)
+        .and_then(|index| replay.frames.get(index))
+    else {
+        return;
+    };
+    let matches = actions.player_movement == recorded.actions.player_movement
+        && actions.gait == recorded.actions.gait;
+    if !matches && actions_diverged.is_none() {
+        error!(
+            "Replay out of sync at frame {}: actions are {actions:?}, recorded {:?}",
+            *frame - 1,
+            recorded.actions
+        );
+        *actions_diverged = Some(*frame - 1);
+    }
+}
+
+fn replay_frame(
+    mut raw_input: RawInputWriter,
+    mut state: ResMut<ReplayState>,
+    player: Query<&Transform, (With<Player>, Without<ThirdPersonCamera>)>,
+    mut camera: Query<(&mut Transform, &mut GlobalTransform), With<ThirdPersonCamera>>,
+    mut app_exit: EventWriter<AppExit>,
+    mut commands: Commands,
+) {
+    let ReplayState::Replaying {
+        replay,
+        frame,
+        actions_diverged,
+    } = &mut *state
+    else {
+        return;
+    };
+    if let Some(recorded) = replay.frames.get(*frame) {
+        raw_input.write(&recorded.input);
+        if *frame == 0 {
+            raw_input.connect_gamepads(&replay.gamepads);
+        }
+        if let (Some(recorded), Ok((mut transform, mut global_transform))) =
+            (recorded.camera, camera.get_single_mut())
+        {
+            *transform = recorded.compute_transform();
+            *global_transform = recorded;
+        }
+        *frame += 1;
+        return;
+    }
+
+    let outcome = ReplayOutcome {
+        recorded: replay.final_position,
+        replayed: player
+            .get_single()
+            .ok()
+            .map(|transform| transform.translation),
+        actions_diverged: *actions_diverged,
+    };
+    if outcome.in_sync() {
+        info!("Replay finished after {} frames", replay.frames.len());
+    } else {
+        error!(
+            "Replay out of sync: player ended at {:?}, recorded at {:?}",
+            outcome.replayed, outcome.recorded
+        );
+    }
+    *state = ReplayState::Off;
+    commands.insert_resource(outcome);
+    commands.insert_resource(TimeUpdateStrategy::Automatic);
+    app_exit.send(AppExit);
+}
+
+fn finish_recording(
+    mut state: ResMut<ReplayState>,
+    player: Query<&Transform, With<Player>>,
+    mut commands: Commands,
+) {
+    let ReplayState::Recording { path, replay, .. } = &mut *state else {
+        return;
+    };
+    if replay.frames.is_empty() {
+        return;
+    }
+    replay.version = REPLAY_VERSION;
+    replay.final_position = player
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation);
+    match replay.save(path) {
+        Ok(()) => info!(
+            "Saved replay of {} frames to {}",
+            replay.frames.len(),
+            path.display()
+        ),
+        Err(error) => error!("Failed to save replay to {}: {error}", path.display()),
+    }
+    // only the first session is recorded, so the file matches what `--replay` plays back
+    *state = ReplayState::Off;
+    commands.insert_resource(TimeUpdateStrategy::Automatic);
+}
+
+/// Plays the replay given with `--replay` without a window, rendering or audio
+/// Fails if the player does not end where it did in the recording. With `--record <file>` the replay is
+/// saved again with the final position it reaches now, e.g. after a deliberate change to the movement.
+pub fn run_headless(mut args: impl Iterator<Item = String>) -> ExitCode {
+    let (mut replay_path, mut record_path) = (None, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => replay_path = args.next(),
+            "--record" => record_path = args.next(),
+            _ => {}
+        }
+    }
+    let Some(replay_path) = replay_path else {
+        eprintln!("A headless run needs a replay to play: --headless --replay <file>");
+        return ExitCode::FAILURE;
+    };
+    let result = Replay::load(Path::new(&replay_path)).and_then(|mut replay| {
+        let outcome = play_headless(replay.clone())?;
+        if let Some(record_path) = &record_path {
+            replay.final_position = outcome.replayed;
+            replay.save(Path::new(record_path))?;
+        }
+        Ok(outcome)
+    });
+    match result {
+        Ok(outcome) if outcome.in_sync() || record_path.is_some() => ExitCode::SUCCESS,
+        Ok(outcome) => {
+            eprintln!(
+                "Replay {replay_path} out of sync: player ended at {:?}, recorded at {:?}",
+                outcome.replayed, outcome.recorded
+            );
+            ExitCode::FAILURE
+        }
+        Err(error) => {
+            eprintln!("Failed to replay {replay_path}: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Plays a replay back in an app with only the input, stage, physics and player logic, until it is done
+pub fn play_headless(replay: Replay) -> Result<ReplayOutcome, ReplayError> {
+    let frame_limit = replay.frames.len() + HEADLESS_LOADING_FRAMES;
+    let mut app = headless_app();
+    app.add_loading_state(LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu))
+        .add_collection_to_loading_state::<_, StageAssets>(GameState::Loading)
+        .insert_resource(ReplayState::Replaying {
+            replay,
+            frame: 0,
+            actions_diverged: None,
+        })
+        .add_systems(OnEnter(GameState::Playing), spawn_headless_camera)
+        .add_plugins((
+            ActionsPlugin,
+            PhysicsPlugin::default(),
+            StagePlugin,
+            CollisionPlugin,
+            PlayerPlugin,
+            ReplayPlugin,
+        ));
+    app.finish();
+    app.cleanup();
+
+    for _ in 0..frame_limit {
+        app.update();
+        if let Some(outcome) = app.world.get_resource::<ReplayOutcome>() {
+            return Ok(*outcome);
+        }
+    }
+    Err(ReplayError::Unfinished(frame_limit))
+}
+
+// stands in for the camera of the game, which is moved to the recorded place every frame
+fn spawn_headless_camera(mut commands: Commands) {
+    commands.spawn((ThirdPersonCamera::default(), TransformBundle::default()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_in_replay_ends_where_it_was_recorded() {
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/replays/arena_walk.replay"
+        ));
+        let replay = Replay::load(path).expect("replay should load");
+        let outcome = play_headless(replay).expect("replay should finish");
+        assert!(outcome.recorded.is_some());
+        assert!(
+            outcome.in_sync(),
+            "player ended at {:?}, recorded at {:?}",
+            outcome.replayed,
+            outcome.recorded
+        );
+    }
+}
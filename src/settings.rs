@@ -0,0 +1,233 @@
+use crate::audio::AudioChannelKind;
+use crate::config;
+use crate::{DespawnOnExit, GameState};
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::window::{PresentMode, PrimaryWindow, WindowMode, WindowRef, WindowResized};
+use bevy_third_person_camera::ThirdPersonCamera;
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+/// Lowest allowed [`GameSettings::resolution_scale`]
+pub const MIN_RESOLUTION_SCALE: f32 = 0.5;
+
+pub struct SettingsPlugin;
+
+/// This plugin loads the [`GameSettings`] on startup, applies them whenever they change and saves them
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = GameSettings::load();
+        app.insert_resource(settings.msaa())
+            .insert_resource(settings)
+            .add_systems(
+                Update,
+                (
+                    apply_window_settings.run_if(resource_changed::<GameSettings>()),
+                    apply_resolution_scale.run_if(
+                        resource_changed::<GameSettings>()
+                            .or_else(on_event::<WindowResized>())
+                            .or_else(camera_added),
+                    ),
+                    save_settings.run_if(
+                        resource_changed::<GameSettings>()
+                            .and_then(not(resource_added::<GameSettings>())),
+                    ),
+                ),
+            );
+    }
+}
+
+/// Player preferences, loaded from and saved to `settings.ron` in the `config` folder next to the executable
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameSettings {
+    /// Samples per pixel, one of 1 (off), 2, 4 or 8
+    pub msaa_samples: u32,
+    pub vsync: bool,
+    pub window_mode: WindowMode,
+    /// Fraction of the window resolution the 3D view is rendered at
+    pub resolution_scale: f32,
+    /// Volumes between 0 and 1, the channel volumes are multiplied by the master volume
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub ambience_volume: f32,
+    pub ui_volume: f32,
+    /// Channels that are silent, without losing their volume
+    pub muted_channels: Vec<AudioChannelKind>,
+    /// Multiplies how fast the mouse, gamepad and touch controls turn the camera
+    pub camera_sensitivity: f32,
+    pub invert_camera_y: bool,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        GameSettings {
+            msaa_samples: 1,
+            vsync: true,
+            window_mode: WindowMode::Windowed,
+            resolution_scale: 1.0,
+            master_volume: 1.0,
+            music_volume: 0.7,
+            sfx_volume: 1.0,
+            ambience_volume: 0.8,
+            ui_volume: 0.8,
+            muted_channels: Vec::new(),
+            camera_sensitivity: 1.0,
+            invert_camera_y: false,
+        }
+    }
+}
+
+impl GameSettings {
+    pub fn load() -> Self {
+        config::load(SETTINGS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        config::save(SETTINGS_FILE, self);
+    }
+
+    /// The volume a channel plays at, including the master volume
+    pub fn channel_volume(&self, channel: AudioChannelKind) -> f32 {
+        if self.is_muted(channel) {
+            return 0.;
+        }
+        let volume = match channel {
+            AudioChannelKind::Music => self.music_volume,
+            AudioChannelKind::Sfx => self.sfx_volume,
+            AudioChannelKind::Ambience => self.ambience_volume,
+            AudioChannelKind::Ui => self.ui_volume,
+        };
+        self.master_volume * volume
+    }
+
+    pub fn is_muted(&self, channel: AudioChannelKind) -> bool {
+        self.muted_channels.contains(&channel)
+    }
+
+    pub fn set_muted(&mut self, channel: AudioChannelKind, muted: bool) {
+        self.muted_channels.retain(|muted| *muted != channel);
+        if muted {
+            self.muted_channels.push(channel);
+        }
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        match self.msaa_samples {
+            2 => Msaa::Sample2,
+            4 => Msaa::Sample4,
+            8 => Msaa::Sample8,
+            _ => Msaa::Off,
+        }
+    }
+}
+
+fn apply_window_settings(
+    settings: Res<GameSettings>,
+    mut msaa: ResMut<Msaa>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    msaa.set_if_neq(settings.msaa());
+    let Ok(mut window) = window.get_single_mut() else {
+        return;
+    };
+    let present_mode = if settings.vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    };
+    if window.present_mode != present_mode {
+        window.present_mode = present_mode;
+    }
+    if window.mode != settings.window_mode {
+        window.mode = settings.window_mode;
+    }
+}
+
+/// Shows the 3D view while it is rendered below the window resolution
+#[derive(Component)]
+struct ScaledView;
+
+fn camera_added(cameras: Query<(), Added<ThirdPersonCamera>>) -> bool {
+    !cameras.is_empty()
+}
+
+// Below full scale the 3D camera renders into an image, which is stretched over the window as a sprite
+// and drawn by the overlay camera together with the UI.
+fn apply_resolution_scale(
+    mut commands: Commands,
+    settings: Res<GameSettings>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut images: ResMut<Assets<Image>>,
+    mut cameras: Query<&mut Camera, With<ThirdPersonCamera>>,
+    mut views: Query<(Entity, &mut Sprite, &Handle<Image>), With<ScaledView>>,
+) {
+    let (Ok(window), Ok(mut camera)) = (window.get_single(), cameras.get_single_mut()) else {
+        return;
+    };
+    let scale = settings.resolution_scale.clamp(MIN_RESOLUTION_SCALE, 1.);
+    if scale >= 1. {
+        camera.target = RenderTarget::Window(WindowRef::Primary);
+        for (entity, ..) in &views {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    let size = Extent3d {
+        width: ((window.physical_width() as f32 * scale) as u32).max(1),
+        height: ((window.physical_height() as f32 * scale) as u32).max(1),
+        depth_or_array_layers: 1,
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+    if let Ok((_, mut sprite, image)) = views.get_single_mut() {
+        if let Some(image) = images.get_mut(image) {
+            if image.texture_descriptor.size != size {
+                image.resize(size);
+            }
+        }
+        sprite.custom_size = Some(window_size);
+        return;
+    }
+
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    // fills the image with zeroes
+    image.resize(size);
+    let image = images.add(image);
+    camera.target = RenderTarget::Image(image.clone());
+    commands.spawn((
+        SpriteBundle {
+            texture: image,
+            sprite: Sprite {
+                custom_size: Some(window_size),
+                ..default()
+            },
+            ..default()
+        },
+        ScaledView,
+        DespawnOnExit(GameState::Playing),
+    ));
+}
+
+fn save_settings(settings: Res<GameSettings>) {
+    settings.save();
+}
@@ -1,12 +1,13 @@
 use bevy::{
-    core_pipeline::Skybox,
+    core_pipeline::{clear_color::ClearColorConfig, Skybox},
     prelude::*,
     render::render_resource::{TextureViewDescriptor, TextureViewDimension},
 };
 use bevy_third_person_camera::{CameraFocusModifier, Offset, ThirdPersonCamera, Zoom};
 use std::f32::consts::{E, PI};
 
-use crate::{loading::Skyboxes, GameState};
+use crate::actions::{emit_action_events, ActionBuffer, GameControl};
+use crate::{loading::Skyboxes, DespawnOnExit, GameState, PauseState};
 
 pub struct ThirdDimensionPlugin;
 
@@ -16,36 +17,71 @@ impl Plugin for ThirdDimensionPlugin {
             .add_systems(
                 Update,
                 (
-                    cycle_cubemap_asset,
+                    cycle_cubemap_asset
+                        .after(emit_action_events)
+                        .run_if(in_state(PauseState::Running)),
                     asset_loaded.after(cycle_cubemap_asset),
-                    animate_light_direction,
+                    animate_light_direction.run_if(in_state(PauseState::Running)),
                 )
                     .run_if(in_state(GameState::Playing)),
             );
     }
 }
 
+/// The skybox currently shown, which sets the mood of the stage
 #[derive(Resource)]
-struct Cubemap {
+pub struct Cubemap {
     is_loaded: bool,
-    index: u8,
+    pub environment: Environment,
     image_handle: Handle<Image>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Environment {
+    City,
+    Church,
+    Forest,
+    TownSquare,
+    Mountainside,
+}
+
+impl Environment {
+    const ALL: [Environment; 5] = [
+        Environment::City,
+        Environment::Church,
+        Environment::Forest,
+        Environment::TownSquare,
+        Environment::Mountainside,
+    ];
+
+    fn image(self, skyboxes: &Skyboxes) -> Handle<Image> {
+        match self {
+            Environment::City => skyboxes.city.clone(),
+            Environment::Church => skyboxes.church.clone(),
+            Environment::Forest => skyboxes.forest.clone(),
+            Environment::TownSquare => skyboxes.town_square.clone(),
+            Environment::Mountainside => skyboxes.mountainside.clone(),
+        }
+    }
+}
+
 // fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 fn setup(mut commands: Commands, asset_server: Res<Skyboxes>) {
     // directional 'sun' light
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            illuminance: 32000.0,
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                illuminance: 32000.0,
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 2.0, 0.0)
+                .with_rotation(Quat::from_rotation_x(-PI / 4.)),
             ..default()
         },
-        transform: Transform::from_xyz(0.0, 2.0, 0.0)
-            .with_rotation(Quat::from_rotation_x(-PI / 4.)),
-        ..default()
-    });
+        DespawnOnExit(GameState::Playing),
+    ));
 
-    let skybox_handle = asset_server.city.clone();
+    let skybox_handle = Environment::City.image(&asset_server);
     // camera
     commands.spawn((
         Camera3dBundle {
@@ -58,6 +94,8 @@ fn setup(mut commands: Commands, asset_server: Res<Skyboxes>) {
             aim_enabled: true,
             aim_zoom: 0.7,
             zoom_enabled: false,
+            // the mouse orbits through Actions::camera_rotation like the other devices
+            mouse_sensitivity: 0.,
             zoom: Zoom::new(1.5, 5.0),
             offset_enabled: true,
             offset: Offset::new(0.4, 0.0),
@@ -75,6 +113,22 @@ fn setup(mut commands: Commands, asset_server: Res<Skyboxes>) {
             ..default()
         },
         Skybox(skybox_handle.clone()),
+        // the overlay camera draws the UI, so it stays sharp when the 3D view is rendered at a lower resolution
+        UiCameraConfig { show_ui: false },
+        DespawnOnExit(GameState::Playing),
+    ));
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                order: 1,
+                ..default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..default()
+        },
+        DespawnOnExit(GameState::Playing),
     ));
 
     // ambient light
@@ -87,7 +141,7 @@ fn setup(mut commands: Commands, asset_server: Res<Skyboxes>) {
 
     commands.insert_resource(Cubemap {
         is_loaded: false,
-        index: 0,
+        environment: Environment::City,
         image_handle: skybox_handle,
     });
 }
@@ -95,23 +149,17 @@ fn setup(mut commands: Commands, asset_server: Res<Skyboxes>) {
 fn cycle_cubemap_asset(
     mut cubemap: ResMut<Cubemap>,
     asset_server: Res<Skyboxes>,
-    key_input: Res<Input<KeyCode>>,
+    mut action_buffer: ResMut<ActionBuffer>,
 ) {
-    if key_input.just_pressed(KeyCode::Space) {
-        let mut new_index = rand::random::<u8>() % 5;
-        while new_index == cubemap.index {
-            new_index = rand::random::<u8>() % 5;
+    // a press while the previous skybox is still being swapped in waits in the buffer
+    if cubemap.is_loaded && action_buffer.consume(GameControl::CycleSkybox) {
+        let mut environment = cubemap.environment;
+        while environment == cubemap.environment {
+            environment = Environment::ALL[rand::random::<usize>() % Environment::ALL.len()];
         }
 
-        cubemap.index = new_index;
-        cubemap.image_handle = match new_index {
-            0 => asset_server.city.clone(),
-            1 => asset_server.church.clone(),
-            2 => asset_server.forest.clone(),
-            3 => asset_server.town_square.clone(),
-            4 => asset_server.mountainside.clone(),
-            _ => unreachable!(),
-        };
+        cubemap.environment = environment;
+        cubemap.image_handle = environment.image(&asset_server);
         cubemap.is_loaded = false;
     }
 }
@@ -0,0 +1,440 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use super::definition::{StageMaterial, Surface};
+use super::StageObject;
+use crate::{DespawnOnExit, GameState};
+
+/// Ground and boundary walls of a stage, generated from a footprint on the XZ plane
+/// The ground surface lies at `y = 0` and the walls stand on the outside of every footprint edge.
+/// It is also inserted as a component on the generated ground entity.
+#[derive(Component, Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArenaBounds {
+    pub footprint: Footprint,
+    pub wall_height: f32,
+    #[serde(default = "default_wall_thickness")]
+    pub wall_thickness: f32,
+    pub ground_material: StageMaterial,
+    pub wall_material: StageMaterial,
+    #[serde(default)]
+    pub ground_surface: Surface,
+}
+
+fn default_wall_thickness() -> f32 {
+    0.5
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum Footprint {
+    /// Axis aligned rectangle centered on the origin
+    Rectangle { width: f32, depth: f32 },
+    /// Polygon of `(x, z)` corners in order (either winding), which may be concave but must not cross itself
+    Polygon { points: Vec<(f32, f32)> },
+}
+
+impl Footprint {
+    pub fn corners(&self) -> Vec<Vec2> {
+        match self {
+            Footprint::Rectangle { width, depth } => {
+                let (x, z) = (width / 2., depth / 2.);
+                vec![
+                    Vec2::new(-x, -z),
+                    Vec2::new(x, -z),
+                    Vec2::new(x, z),
+                    Vec2::new(-x, z),
+                ]
+            }
+            Footprint::Polygon { points } => {
+                points.iter().map(|(x, z)| Vec2::new(*x, *z)).collect()
+            }
+        }
+    }
+}
+
+impl ArenaBounds {
+    pub(super) fn validate(&self) -> Result<(), String> {
+        if !(self.wall_height.is_finite() && self.wall_height > 0.) {
+            return Err(format!("wall height {} must be positive", self.wall_height));
+        }
+        if !(self.wall_thickness.is_finite() && self.wall_thickness > 0.) {
+            return Err(format!(
+                "wall thickness {} must be positive",
+                self.wall_thickness
+            ));
+        }
+        let corners = self.footprint.corners();
+        if corners.len() < 3 {
+            return Err("footprint needs at least three corners".to_string());
+        }
+        if corners.iter().any(|c| !c.is_finite()) {
+            return Err("footprint corners must be finite".to_string());
+        }
+        let count = corners.len();
+        for i in 0..count {
+            let (a, b, c) = (
+                corners[i],
+                corners[(i + 1) % count],
+                corners[(i + 2) % count],
+            );
+            if a == b {
+                return Err(format!(
+                    "footprint corner {} repeats the one before",
+                    (i + 1) % count
+                ));
+            }
+            if (b - a).perp_dot(c - b) == 0. && (b - a).dot(c - b) < 0. {
+                return Err(format!(
+                    "footprint folds back on itself at corner {}",
+                    (i + 1) % count
+                ));
+            }
+        }
+        for i in 0..count {
+            // edges sharing a corner always touch, and were checked above
+            for j in i + 2..count {
+                if i == 0 && j == count - 1 {
+                    continue;
+                }
+                let first = (corners[i], corners[(i + 1) % count]);
+                let second = (corners[j], corners[(j + 1) % count]);
+                if segments_intersect(first, second) {
+                    return Err(format!("footprint edges {i} and {j} cross each other"));
+                }
+            }
+        }
+        if signed_area(&corners) == 0. {
+            return Err("footprint encloses no area".to_string());
+        }
+        Ok(())
+    }
+
+    fn centroid(corners: &[Vec2]) -> Vec2 {
+        corners.iter().sum::<Vec2>() / corners.len() as f32
+    }
+
+    fn ground_mesh(corners: &[Vec2]) -> Mesh {
+        let center = Self::centroid(corners);
+        let extent = corners
+            .iter()
+            .fold(Vec2::ZERO, |extent, c| extent.max((*c - center).abs()))
+            .max(Vec2::splat(f32::EPSILON));
+        let positions: Vec<[f32; 3]> = corners.iter().map(|c| [c.x, 0., c.y]).collect();
+        let normals = vec![[0., 1., 0.]; corners.len()];
+        let uvs: Vec<[f32; 2]> = corners
+            .iter()
+            .map(|c| {
+                let uv = (*c - center) / extent * 0.5 + 0.5;
+                [uv.x, uv.y]
+            })
+            .collect();
+        let indices = triangulate(corners).into_iter().flatten().collect();
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+
+    fn ground_collider(corners: &[Vec2]) -> Collider {
+        let vertices = corners.iter().map(|c| Vec3::new(c.x, 0., c.y)).collect();
+        Collider::trimesh(vertices, triangulate(corners))
+    }
+
+    // One wall on the outside of every footprint edge, in the order of the edges
+    fn walls(&self, corners: &[Vec2]) -> Vec<Wall> {
+        let interior_side = signed_area(corners).signum();
+        let count = corners.len();
+        // walls only reach past convex corners, at the others they would stick into the arena
+        let convex: Vec<bool> = (0..count)
+            .map(|i| {
+                let (previous, corner, next) = (
+                    corners[(i + count - 1) % count],
+                    corners[i],
+                    corners[(i + 1) % count],
+                );
+                (corner - previous).perp_dot(next - corner) * interior_side > 0.
+            })
+            .collect();
+        (0..count)
+            .map(|i| {
+                let (start, end) = (corners[i], corners[(i + 1) % count]);
+                let edge = end - start;
+                let length = edge.length();
+                let direction = edge / length;
+                // the interior is on the left of the edges when the area is positive
+                let outward = -direction.perp() * interior_side;
+                // the extra thickness at convex corners closes the gaps between the walls
+                let extension = |convex: bool| if convex { self.wall_thickness / 2. } else { 0. };
+                let (start_extension, end_extension) =
+                    (extension(convex[i]), extension(convex[(i + 1) % count]));
+                Wall {
+                    // push the wall outwards so its inner face lines up with the footprint edge
+                    center: (start + end) / 2.
+                        + direction * (end_extension - start_extension) / 2.
+                        + outward * self.wall_thickness / 2.,
+                    direction,
+                    length: length + start_extension + end_extension,
+                }
+            })
+            .collect()
+    }
+}
+
+// A boundary wall box on the XZ plane
+struct Wall {
+    center: Vec2,
+    // along the footprint edge
+    direction: Vec2,
+    length: f32,
+}
+
+// Twice the area enclosed by the corners, positive when the interior lies to the left of the edges
+fn signed_area(corners: &[Vec2]) -> f32 {
+    (0..corners.len())
+        .map(|i| corners[i].perp_dot(corners[(i + 1) % corners.len()]))
+        .sum()
+}
+
+fn segments_intersect((a, b): (Vec2, Vec2), (c, d): (Vec2, Vec2)) -> bool {
+    let side = |from: Vec2, to: Vec2, point: Vec2| (to - from).perp_dot(point - from);
+    let on_segment = |from: Vec2, to: Vec2, point: Vec2| {
+        side(from, to, point) == 0.
+            && point.cmpge(from.min(to)).all()
+            && point.cmple(from.max(to)).all()
+    };
+    let crossing = side(c, d, a) * side(c, d, b) < 0. && side(a, b, c) * side(a, b, d) < 0.;
+    crossing
+        || on_segment(c, d, a)
+        || on_segment(c, d, b)
+        || on_segment(a, b, c)
+        || on_segment(a, b, d)
+}
+
+// Splits a validated footprint into triangles by clipping ears
+// The triangles index into the corners and wind counter clockwise seen from above, so they face up.
+fn triangulate(corners: &[Vec2]) -> Vec<[u32; 3]> {
+    let mut remaining: Vec<u32> = (0..corners.len() as u32).collect();
+    // the ear test below expects the interior on the left of the edges
+    if signed_area(corners) < 0. {
+        remaining.reverse();
+    }
+    let corner = |index: u32| corners[index as usize];
+    let side = |from: Vec2, to: Vec2, point: Vec2| (to - from).perp_dot(point - from);
+    let mut triangles = Vec::with_capacity(corners.len() - 2);
+    let mut i = 0;
+    // every full pass without an ear means the outline was not simple after all
+    let mut passes_without_ear = 0;
+    while remaining.len() > 2 && passes_without_ear < remaining.len() {
+        let count = remaining.len();
+        let (previous, current, next) = (
+            remaining[(i + count - 1) % count],
+            remaining[i % count],
+            remaining[(i + 1) % count],
+        );
+        let (a, b, c) = (corner(previous), corner(current), corner(next));
+        let turn = side(a, b, c);
+        let is_ear = turn > 0.
+            && remaining
+                .iter()
+                .filter(|index| ![previous, current, next].contains(index))
+                .all(|index| {
+                    let point = corner(*index);
+                    side(a, b, point) < 0. || side(b, c, point) < 0. || side(c, a, point) < 0.
+                });
+        if turn == 0. || is_ear {
+            // a corner in a straight line needs no triangle of its own
+            if is_ear {
+                triangles.push([previous, next, current]);
+            }
+            remaining.remove(i % count);
+            passes_without_ear = 0;
+        } else {
+            i += 1;
+            passes_without_ear += 1;
+        }
+        i %= remaining.len().max(1);
+    }
+    triangles
+}
+
+pub(super) fn spawn_arena(
+    commands: &mut Commands,
+    arena: &ArenaBounds,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let corners = arena.footprint.corners();
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(ArenaBounds::ground_mesh(&corners)),
+            material: materials.add(arena.ground_material.material()),
+            ..default()
+        },
+        ArenaBounds::ground_collider(&corners),
+        RigidBody::Fixed,
+        arena.ground_surface,
+        arena.clone(),
+        Name::new("arena ground"),
+        StageObject,
+        DespawnOnExit(GameState::Playing),
+    ));
+
+    let wall_material = materials.add(arena.wall_material.material());
+    for (index, wall) in arena.walls(&corners).into_iter().enumerate() {
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(
+                    shape::Box::new(wall.length, arena.wall_height, arena.wall_thickness).into(),
+                ),
+                material: wall_material.clone(),
+                transform: Transform::from_xyz(
+                    wall.center.x,
+                    arena.wall_height / 2.,
+                    wall.center.y,
+                )
+                .with_rotation(Quat::from_rotation_y(f32::atan2(
+                    -wall.direction.y,
+                    wall.direction.x,
+                ))),
+                ..default()
+            },
+            Collider::cuboid(
+                wall.length / 2.,
+                arena.wall_height / 2.,
+                arena.wall_thickness / 2.,
+            ),
+            RigidBody::Fixed,
+            Name::new(format!("arena wall {index}")),
+            StageObject,
+            DespawnOnExit(GameState::Playing),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an L shape, concave at (1, 1)
+    const L_SHAPE: [(f32, f32); 6] = [(0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 2.), (0., 2.)];
+
+    fn arena(points: &[(f32, f32)]) -> ArenaBounds {
+        let material = StageMaterial {
+            color: Color::WHITE,
+            perceptual_roughness: 0.5,
+            metallic: 0.,
+        };
+        ArenaBounds {
+            footprint: Footprint::Polygon {
+                points: points.to_vec(),
+            },
+            wall_height: 1.,
+            wall_thickness: 0.5,
+            ground_material: material.clone(),
+            wall_material: material,
+            ground_surface: Surface::default(),
+        }
+    }
+
+    fn both_windings(points: &[(f32, f32)]) -> [Vec<(f32, f32)>; 2] {
+        let mut reversed = points.to_vec();
+        reversed.reverse();
+        [points.to_vec(), reversed]
+    }
+
+    // even-odd rule
+    fn contains(corners: &[Vec2], point: Vec2) -> bool {
+        let mut inside = false;
+        for i in 0..corners.len() {
+            let (a, b) = (corners[i], corners[(i + 1) % corners.len()]);
+            if (a.y > point.y) != (b.y > point.y) {
+                let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if point.x < x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+
+    #[test]
+    fn validate_rejects_broken_arenas() {
+        assert!(arena(&[(0., 0.), (1., 0.)]).validate().is_err());
+        // a bow tie, whose middle edges cross
+        assert!(arena(&[(0., 0.), (1., 1.), (1., 0.), (0., 1.)])
+            .validate()
+            .is_err());
+        assert!(arena(&[(0., 0.), (1., 0.), (1., 0.), (0., 1.)])
+            .validate()
+            .is_err());
+        assert!(arena(&[(0., 0.), (1., 0.), (2., 0.)]).validate().is_err());
+
+        let mut flat = arena(&L_SHAPE);
+        flat.wall_height = 0.;
+        assert!(flat.validate().is_err());
+    }
+
+    #[test]
+    fn validate_accepts_concave_arenas() {
+        for points in both_windings(&L_SHAPE) {
+            assert_eq!(arena(&points).validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn ground_triangles_cover_the_footprint_and_face_up() {
+        for points in both_windings(&L_SHAPE) {
+            let corners = arena(&points).footprint.corners();
+            let triangles = triangulate(&corners);
+            assert_eq!(triangles.len(), corners.len() - 2);
+            let mut area = 0.;
+            for [a, b, c] in triangles {
+                let (a, b, c) = (
+                    corners[a as usize],
+                    corners[b as usize],
+                    corners[c as usize],
+                );
+                let turn = (b - a).perp_dot(c - b);
+                assert!(turn < 0., "triangle {a} {b} {c} faces down");
+                area -= turn / 2.;
+            }
+            assert_eq!(area, 3.);
+        }
+    }
+
+    #[test]
+    fn walls_stand_outside_every_edge() {
+        for points in both_windings(&L_SHAPE) {
+            let arena = arena(&points);
+            let corners = arena.footprint.corners();
+            let walls = arena.walls(&corners);
+            assert_eq!(walls.len(), corners.len());
+            for (index, wall) in walls.iter().enumerate() {
+                // the inner face of the wall lies on its edge
+                let start = corners[index];
+                let distance = (wall.center - start).perp_dot(wall.direction).abs();
+                assert!((distance - arena.wall_thickness / 2.).abs() < 1e-5);
+                assert!(!contains(&corners, wall.center), "wall {index} is inside");
+            }
+        }
+    }
+
+    #[test]
+    fn walls_only_reach_past_convex_corners() {
+        let arena = arena(&L_SHAPE);
+        let lengths: Vec<f32> = arena
+            .walls(&arena.footprint.corners())
+            .iter()
+            .map(|wall| wall.length)
+            .collect();
+        // the edges on both sides of the concave corner stop there
+        assert_eq!(lengths, [2.5, 1.5, 1.25, 1.25, 1.5, 2.5]);
+    }
+}
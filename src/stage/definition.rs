@@ -0,0 +1,395 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use bevy_rapier3d::prelude::{Collider, RigidBody};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::arena::ArenaBounds;
+use super::zone::ZoneKind;
+use crate::audio::Playlist;
+
+/// A stage (arena) described in a `.stage.ron` asset file.
+/// Every entry in `entities` is spawned by `build_stage` when the stage is entered.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageDefinition {
+    /// Where the player is placed when the stage is entered
+    #[serde(default)]
+    pub spawn_point: [f32; 3],
+    /// Generated ground and boundary walls
+    #[serde(default)]
+    pub arena: Option<ArenaBounds>,
+    #[serde(default)]
+    pub entities: Vec<StageEntity>,
+    /// Sensor volumes reporting `ZoneEntered` / `ZoneExited` events
+    #[serde(default)]
+    pub zones: Vec<StageZone>,
+    /// Music of the stage, instead of the one picked by the skybox
+    #[serde(default)]
+    pub music: Option<Playlist>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageEntity {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub mesh: StageMesh,
+    pub material: StageMaterial,
+    #[serde(default)]
+    pub transform: StageTransform,
+    #[serde(default)]
+    pub collider: Option<StageCollider>,
+    #[serde(default)]
+    pub rigid_body: Option<StageRigidBody>,
+    #[serde(default)]
+    pub interaction: Option<StageInteraction>,
+    /// What the entity sounds like when walked on
+    #[serde(default)]
+    pub surface: Surface,
+    /// Sound played from the entity's position
+    #[serde(default)]
+    pub sound: Option<StageSound>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum StageMesh {
+    Plane { size: f32 },
+    Cube { size: f32 },
+    Box { x: f32, y: f32, z: f32 },
+    Cylinder { radius: f32, height: f32 },
+    Sphere { radius: f32 },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageMaterial {
+    pub color: Color,
+    #[serde(default = "default_roughness")]
+    pub perceptual_roughness: f32,
+    #[serde(default)]
+    pub metallic: f32,
+}
+
+fn default_roughness() -> f32 {
+    0.5
+}
+
+/// Rotation is given as XYZ euler angles in degrees to keep the files readable.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageTransform {
+    #[serde(default)]
+    pub translation: [f32; 3],
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+}
+
+fn default_scale() -> [f32; 3] {
+    [1., 1., 1.]
+}
+
+impl Default for StageTransform {
+    fn default() -> Self {
+        StageTransform {
+            translation: [0., 0., 0.],
+            rotation: [0., 0., 0.],
+            scale: default_scale(),
+        }
+    }
+}
+
+/// Collider shapes use the same half extents / half heights as `bevy_rapier3d`
+#[derive(Debug, Deserialize)]
+pub enum StageCollider {
+    Cuboid { half_extents: [f32; 3] },
+    Ball { radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+    Capsule { half_height: f32, radius: f32 },
+}
+
+#[derive(Debug, Deserialize)]
+pub enum StageRigidBody {
+    Fixed,
+    Dynamic,
+    KinematicPositionBased,
+    KinematicVelocityBased,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageZone {
+    pub id: String,
+    pub kind: ZoneKind,
+    pub shape: StageCollider,
+    #[serde(default)]
+    pub transform: StageTransform,
+}
+
+/// Gameplay reaction to the player touching the entity, see [`crate::collision`]
+/// Pickups and triggers are spawned as sensors, so the player passes through them.
+#[derive(Debug, Deserialize)]
+pub enum StageInteraction {
+    Hazard,
+    Pickup,
+    Trigger,
+}
+
+/// A sound from the `SoundLibrary`, see [`crate::audio::AudioEmitter`]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageSound {
+    pub name: String,
+    /// Distance in meters at which the sound fades out completely
+    #[serde(default = "default_sound_range")]
+    pub range: f32,
+}
+
+fn default_sound_range() -> f32 {
+    15.0
+}
+
+impl StageSound {
+    fn validate(&self) -> Result<(), String> {
+        if self.range.is_finite() && self.range > 0. {
+            Ok(())
+        } else {
+            Err(format!("sound range {} must be positive", self.range))
+        }
+    }
+}
+
+/// Material of a walkable collider, which picks the player's footstep sounds
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum Surface {
+    #[default]
+    Stone,
+    Grass,
+    Metal,
+    Wood,
+}
+
+impl StageMesh {
+    pub fn mesh(&self) -> Mesh {
+        match *self {
+            StageMesh::Plane { size } => shape::Plane::from_size(size).into(),
+            StageMesh::Cube { size } => shape::Cube::new(size).into(),
+            StageMesh::Box { x, y, z } => shape::Box::new(x, y, z).into(),
+            StageMesh::Cylinder { radius, height } => shape::Cylinder {
+                radius,
+                height,
+                ..default()
+            }
+            .into(),
+            StageMesh::Sphere { radius } => shape::UVSphere {
+                radius,
+                ..default()
+            }
+            .into(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let dimensions = match *self {
+            StageMesh::Plane { size } | StageMesh::Cube { size } => vec![size],
+            StageMesh::Box { x, y, z } => vec![x, y, z],
+            StageMesh::Cylinder { radius, height } => vec![radius, height],
+            StageMesh::Sphere { radius } => vec![radius],
+        };
+        if dimensions.iter().all(|d| d.is_finite() && *d > 0.) {
+            Ok(())
+        } else {
+            Err(format!("mesh {self:?} must have positive dimensions"))
+        }
+    }
+}
+
+impl StageMaterial {
+    pub fn material(&self) -> StandardMaterial {
+        StandardMaterial {
+            base_color: self.color,
+            perceptual_roughness: self.perceptual_roughness,
+            metallic: self.metallic,
+            ..default()
+        }
+    }
+}
+
+impl StageTransform {
+    pub fn transform(&self) -> Transform {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+        Transform::from_translation(Vec3::from_array(self.translation))
+            .with_rotation(Quat::from_euler(EulerRot::XYZ, x, y, z))
+            .with_scale(Vec3::from_array(self.scale))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.scale.iter().any(|s| *s == 0. || !s.is_finite()) {
+            return Err(format!(
+                "scale {:?} must be finite and non-zero",
+                self.scale
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl StageCollider {
+    pub fn collider(&self) -> Collider {
+        match *self {
+            StageCollider::Cuboid {
+                half_extents: [x, y, z],
+            } => Collider::cuboid(x, y, z),
+            StageCollider::Ball { radius } => Collider::ball(radius),
+            StageCollider::Cylinder {
+                half_height,
+                radius,
+            } => Collider::cylinder(half_height, radius),
+            StageCollider::Capsule {
+                half_height,
+                radius,
+            } => Collider::capsule_y(half_height, radius),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let dimensions = match *self {
+            // a zero half extent is allowed here to describe flat colliders like the ground
+            StageCollider::Cuboid { half_extents } => half_extents.to_vec(),
+            StageCollider::Ball { radius } => vec![radius],
+            StageCollider::Cylinder {
+                half_height,
+                radius,
+            }
+            | StageCollider::Capsule {
+                half_height,
+                radius,
+            } => vec![half_height, radius],
+        };
+        if dimensions.iter().all(|d| d.is_finite() && *d >= 0.) {
+            Ok(())
+        } else {
+            Err(format!(
+                "collider {self:?} must have non-negative dimensions"
+            ))
+        }
+    }
+}
+
+impl From<&StageRigidBody> for RigidBody {
+    fn from(body: &StageRigidBody) -> Self {
+        match body {
+            StageRigidBody::Fixed => RigidBody::Fixed,
+            StageRigidBody::Dynamic => RigidBody::Dynamic,
+            StageRigidBody::KinematicPositionBased => RigidBody::KinematicPositionBased,
+            StageRigidBody::KinematicVelocityBased => RigidBody::KinematicVelocityBased,
+        }
+    }
+}
+
+impl StageDefinition {
+    pub fn spawn_point(&self) -> Vec3 {
+        Vec3::from_array(self.spawn_point)
+    }
+
+    fn validate(&self) -> Result<(), StageDefinitionError> {
+        if self.arena.is_none() && self.entities.is_empty() {
+            return Err(StageDefinitionError::Empty);
+        }
+        if self
+            .music
+            .as_ref()
+            .is_some_and(|music| music.tracks.is_empty())
+        {
+            return Err(StageDefinitionError::EmptyPlaylist);
+        }
+        if let Some(arena) = &self.arena {
+            arena
+                .validate()
+                .map_err(StageDefinitionError::InvalidArena)?;
+        }
+        for (index, entity) in self.entities.iter().enumerate() {
+            entity
+                .mesh
+                .validate()
+                .and_then(|_| entity.transform.validate())
+                .and_then(|_| entity.collider.as_ref().map_or(Ok(()), |c| c.validate()))
+                .and_then(|_| entity.sound.as_ref().map_or(Ok(()), |s| s.validate()))
+                .map_err(|reason| StageDefinitionError::Invalid {
+                    index,
+                    name: entity.name.clone().unwrap_or_default(),
+                    reason,
+                })?;
+        }
+        for (index, zone) in self.zones.iter().enumerate() {
+            if self.zones[..index].iter().any(|other| other.id == zone.id) {
+                return Err(StageDefinitionError::InvalidZone {
+                    id: zone.id.clone(),
+                    reason: "the id is used by another zone".to_string(),
+                });
+            }
+            zone.shape
+                .validate()
+                .and_then(|_| zone.transform.validate())
+                .map_err(|reason| StageDefinitionError::InvalidZone {
+                    id: zone.id.clone(),
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StageDefinitionError {
+    #[error("could not read stage file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse stage file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("stage file does not contain an arena or any entities")]
+    Empty,
+    #[error("stage music playlist has no tracks")]
+    EmptyPlaylist,
+    #[error("invalid stage arena: {0}")]
+    InvalidArena(String),
+    #[error("invalid stage entity #{index} '{name}': {reason}")]
+    Invalid {
+        index: usize,
+        name: String,
+        reason: String,
+    },
+    #[error("invalid stage zone '{id}': {reason}")]
+    InvalidZone { id: String, reason: String },
+}
+
+#[derive(Default)]
+pub struct StageDefinitionLoader;
+
+impl AssetLoader for StageDefinitionLoader {
+    type Asset = StageDefinition;
+    type Settings = ();
+    type Error = StageDefinitionError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let definition: StageDefinition = ron::de::from_bytes(&bytes)?;
+            definition.validate()?;
+            Ok(definition)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["stage.ron"]
+    }
+}
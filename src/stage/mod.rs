@@ -0,0 +1,346 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::audio::AudioEmitter;
+use crate::collision::{emit_player_collisions, Hazard, Pickup, Trigger};
+use crate::loading::StageAssets;
+use crate::player::{Player, PlayerMotion};
+use crate::{DespawnOnExit, GameState};
+
+use arena::spawn_arena;
+use definition::StageInteraction;
+pub use definition::{StageDefinition, StageDefinitionLoader, Surface};
+use zone::{emit_zone_events, log_zone_events, react_to_zones, spawn_zone};
+pub use zone::{Zone, ZoneEntered, ZoneExited, ZoneKind};
+
+mod arena;
+mod definition;
+mod zone;
+
+pub struct StagePlugin;
+
+/// This plugin spawns the stage described by a [`StageDefinition`] asset
+/// The definitions are loaded together with the other assets during `GameState::Loading`
+/// and are switched through with the [`ChangeStage`] event while playing
+impl Plugin for StagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<StageDefinition>()
+            .init_asset_loader::<StageDefinitionLoader>()
+            .init_resource::<CurrentStage>()
+            .init_resource::<Checkpoint>()
+            .add_event::<ChangeStage>()
+            .add_event::<RespawnPlayer>()
+            .add_event::<ZoneEntered>()
+            .add_event::<ZoneExited>()
+            .add_systems(OnEnter(GameState::Playing), build_stage)
+            .add_systems(
+                Update,
+                (
+                    emit_zone_events.after(emit_player_collisions),
+                    react_to_zones.after(emit_zone_events),
+                    log_zone_events.after(emit_zone_events),
+                    change_stage.after(react_to_zones),
+                    respawn_player.after(react_to_zones),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Index into [`StageAssets::stages`] of the stage that is currently spawned
+#[derive(Resource, Default)]
+pub struct CurrentStage(pub usize);
+
+impl CurrentStage {
+    pub fn definition<'a>(
+        &self,
+        stages: &StageAssets,
+        definitions: &'a Assets<StageDefinition>,
+    ) -> Option<&'a StageDefinition> {
+        stages
+            .stages
+            .get(self.0)
+            .and_then(|handle| definitions.get(handle))
+    }
+}
+
+#[derive(Event)]
+pub enum ChangeStage {
+    /// Go to the following stage, wrapping around after the last one
+    Next,
+    To(usize),
+}
+
+/// Where the player was last seen at a checkpoint zone of the current stage
+#[derive(Resource, Default)]
+pub struct Checkpoint(pub Option<Vec3>);
+
+/// Puts the player back at the last checkpoint, or the stage's spawn point without one
+#[derive(Event)]
+pub struct RespawnPlayer;
+
+/// Marks every entity that was spawned as part of the current stage
+#[derive(Component)]
+pub struct StageObject;
+
+fn build_stage(
+    stages: Res<StageAssets>,
+    current_stage: Res<CurrentStage>,
+    definitions: Res<Assets<StageDefinition>>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut spawner: StageSpawner,
+) {
+    // a restarted stage starts over from its spawn point
+    checkpoint.0 = None;
+    let Some(definition) = current_stage.definition(&stages, &definitions) else {
+        error!("Stage definition is not loaded, nothing to build");
+        return;
+    };
+    spawner.spawn(definition);
+}
+
+fn change_stage(
+    mut events: EventReader<ChangeStage>,
+    stages: Res<StageAssets>,
+    mut current_stage: ResMut<CurrentStage>,
+    definitions: Res<Assets<StageDefinition>>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut spawner: StageSpawner,
+    mut player: Query<(&mut Transform, &mut PlayerMotion), With<Player>>,
+) {
+    // only the last request of a frame matters
+    let Some(change) = events.read().last() else {
+        return;
+    };
+    let next = match *change {
+        ChangeStage::Next => (current_stage.0 + 1) % stages.stages.len().max(1),
+        ChangeStage::To(index) => index,
+    };
+    if next >= stages.stages.len() {
+        warn!("There is no stage with index {next}");
+        return;
+    }
+
+    current_stage.0 = next;
+    let Some(definition) = current_stage.definition(&stages, &definitions) else {
+        error!("Stage definition {next} is not loaded, nothing to build");
+        return;
+    };
+
+    spawner.despawn();
+    spawner.spawn(definition);
+    checkpoint.0 = None;
+    for (mut transform, mut motion) in &mut player {
+        transform.translation = definition.spawn_point();
+        motion.velocity = Vec3::ZERO;
+    }
+}
+
+fn respawn_player(
+    mut events: EventReader<RespawnPlayer>,
+    stages: Res<StageAssets>,
+    current_stage: Res<CurrentStage>,
+    checkpoint: Res<Checkpoint>,
+    definitions: Res<Assets<StageDefinition>>,
+    mut player: Query<(&mut Transform, &mut PlayerMotion), With<Player>>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    let respawn_point = checkpoint.0.unwrap_or_else(|| {
+        current_stage
+            .definition(&stages, &definitions)
+            .map_or(Vec3::ZERO, StageDefinition::spawn_point)
+    });
+    for (mut transform, mut motion) in &mut player {
+        transform.translation = respawn_point;
+        motion.velocity = Vec3::ZERO;
+    }
+}
+
+// Spawns and despawns the entities of a stage
+#[derive(SystemParam)]
+struct StageSpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    stage_objects: Query<'w, 's, Entity, With<StageObject>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+}
+
+impl StageSpawner<'_, '_> {
+    fn despawn(&mut self) {
+        for entity in &self.stage_objects {
+            self.commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    fn spawn(&mut self, definition: &StageDefinition) {
+        spawn_stage(
+            &mut self.commands,
+            definition,
+            &mut self.meshes,
+            &mut self.materials,
+        );
+    }
+}
+
+fn spawn_stage(
+    commands: &mut Commands,
+    definition: &StageDefinition,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    if let Some(arena) = &definition.arena {
+        spawn_arena(commands, arena, meshes, materials);
+    }
+    for entity in &definition.entities {
+        let mut stage_entity = commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(entity.mesh.mesh()),
+                material: materials.add(entity.material.material()),
+                transform: entity.transform.transform(),
+                ..default()
+            },
+            entity.surface,
+            StageObject,
+            DespawnOnExit(GameState::Playing),
+        ));
+        if let Some(name) = &entity.name {
+            stage_entity.insert(Name::new(name.clone()));
+        }
+        if let Some(collider) = &entity.collider {
+            stage_entity.insert(collider.collider());
+        }
+        if let Some(rigid_body) = &entity.rigid_body {
+            stage_entity.insert(RigidBody::from(rigid_body));
+        }
+        if let Some(sound) = &entity.sound {
+            stage_entity.insert(AudioEmitter {
+                sound: sound.name.clone(),
+                range: sound.range,
+            });
+        }
+        match entity.interaction {
+            Some(StageInteraction::Hazard) => {
+                stage_entity.insert(Hazard);
+            }
+            Some(StageInteraction::Pickup) => {
+                stage_entity.insert((Pickup, Sensor));
+            }
+            Some(StageInteraction::Trigger) => {
+                stage_entity.insert((Trigger, Sensor));
+            }
+            None => {}
+        }
+    }
+    for zone in &definition.zones {
+        spawn_zone(commands, zone);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::PlayerCollision;
+    use crate::headless_app;
+
+    // both stages from the assets folder, in the order of `StageAssets`
+    fn stage_definitions() -> [StageDefinition; 2] {
+        [
+            include_str!("../../assets/stages/arena.stage.ron"),
+            include_str!("../../assets/stages/courtyard.stage.ron"),
+        ]
+        .map(|source| ron::de::from_str(source).expect("stage definition should parse"))
+    }
+
+    // ground, walls, entities and zones spawned for a definition
+    fn object_count(definition: &StageDefinition) -> usize {
+        let arena = definition
+            .arena
+            .as_ref()
+            .map_or(0, |arena| 1 + arena.footprint.corners().len());
+        arena + definition.entities.len() + definition.zones.len()
+    }
+
+    fn stage_app() -> (App, Vec<(usize, Vec3)>) {
+        let mut app = headless_app();
+        app.add_plugins(StagePlugin).add_event::<PlayerCollision>();
+
+        let mut expected = Vec::new();
+        let mut handles = Vec::new();
+        let mut definitions = app.world.resource_mut::<Assets<StageDefinition>>();
+        for definition in stage_definitions() {
+            expected.push((object_count(&definition), definition.spawn_point()));
+            handles.push(definitions.add(definition));
+        }
+        app.insert_resource(StageAssets { stages: handles });
+        app.world.spawn((
+            Player,
+            PlayerMotion::default(),
+            TransformBundle::from_transform(Transform::from_xyz(5., 5., 5.)),
+        ));
+        (app, expected)
+    }
+
+    fn stage_objects(app: &mut App) -> usize {
+        app.world
+            .query_filtered::<(), With<StageObject>>()
+            .iter(&app.world)
+            .count()
+    }
+
+    fn player_translation(app: &mut App) -> Vec3 {
+        app.world
+            .query_filtered::<&Transform, With<Player>>()
+            .single(&app.world)
+            .translation
+    }
+
+    fn change_stage(app: &mut App, change: ChangeStage) {
+        app.world.send_event(change);
+        app.update();
+    }
+
+    #[test]
+    fn changing_stage_replaces_stage_objects_and_moves_player() {
+        let (mut app, expected) = stage_app();
+        assert_ne!(expected[0].0, expected[1].0);
+        assert_eq!(stage_objects(&mut app), 0);
+
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Playing);
+        app.update();
+        assert_eq!(stage_objects(&mut app), expected[0].0);
+
+        change_stage(&mut app, ChangeStage::Next);
+        assert_eq!(app.world.resource::<CurrentStage>().0, 1);
+        assert_eq!(stage_objects(&mut app), expected[1].0);
+        assert_eq!(player_translation(&mut app), expected[1].1);
+
+        // wraps around after the last stage
+        change_stage(&mut app, ChangeStage::Next);
+        assert_eq!(app.world.resource::<CurrentStage>().0, 0);
+        assert_eq!(stage_objects(&mut app), expected[0].0);
+        assert_eq!(player_translation(&mut app), expected[0].1);
+
+        change_stage(&mut app, ChangeStage::To(1));
+        assert_eq!(app.world.resource::<CurrentStage>().0, 1);
+        assert_eq!(stage_objects(&mut app), expected[1].0);
+        assert_eq!(player_translation(&mut app), expected[1].1);
+    }
+
+    #[test]
+    fn changing_to_a_missing_stage_keeps_the_current_one() {
+        let (mut app, expected) = stage_app();
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Playing);
+        app.update();
+
+        change_stage(&mut app, ChangeStage::To(2));
+        assert_eq!(app.world.resource::<CurrentStage>().0, 0);
+        assert_eq!(stage_objects(&mut app), expected[0].0);
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use super::definition::StageZone;
+use super::{ChangeStage, Checkpoint, RespawnPlayer, StageObject};
+use crate::collision::{CollisionKind, PlayerCollision};
+use crate::{DespawnOnExit, GameState};
+
+/// What happens when the player walks into a zone
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum ZoneKind {
+    /// Later respawns put the player at the center of this zone
+    Checkpoint,
+    /// Respawns the player at the last checkpoint or the stage's spawn point
+    KillPlane,
+    /// Switches to the given stage, or the next one if none is given
+    Exit {
+        #[serde(default)]
+        stage: Option<usize>,
+    },
+    /// Switches the music to the named track
+    Music { track: String },
+}
+
+/// A sensor volume of the current stage, identified by its `id`
+#[derive(Component, Clone, Debug)]
+pub struct Zone {
+    pub id: String,
+    pub kind: ZoneKind,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ZoneEntered {
+    pub zone: Entity,
+    pub id: String,
+    pub kind: ZoneKind,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ZoneExited {
+    pub zone: Entity,
+    pub id: String,
+    pub kind: ZoneKind,
+}
+
+impl ZoneKind {
+    // sensors are drawn in these colors by the physics debug renderer
+    fn debug_color(&self) -> Color {
+        match self {
+            ZoneKind::Checkpoint => Color::CYAN,
+            ZoneKind::KillPlane => Color::ORANGE_RED,
+            ZoneKind::Exit { .. } => Color::LIME_GREEN,
+            ZoneKind::Music { .. } => Color::VIOLET,
+        }
+    }
+}
+
+pub(super) fn spawn_zone(commands: &mut Commands, zone: &StageZone) {
+    commands.spawn((
+        TransformBundle::from_transform(zone.transform.transform()),
+        zone.shape.collider(),
+        Sensor,
+        ColliderDebugColor(zone.kind.debug_color()),
+        Zone {
+            id: zone.id.clone(),
+            kind: zone.kind.clone(),
+        },
+        Name::new(format!("zone {}", zone.id)),
+        StageObject,
+        DespawnOnExit(GameState::Playing),
+    ));
+}
+
+pub(super) fn emit_zone_events(
+    mut player_collisions: EventReader<PlayerCollision>,
+    zones: Query<&Zone>,
+    mut entered: EventWriter<ZoneEntered>,
+    mut exited: EventWriter<ZoneExited>,
+) {
+    for collision in player_collisions.read() {
+        let Ok(zone) = zones.get(collision.other) else {
+            continue;
+        };
+        match collision.kind {
+            CollisionKind::Started => entered.send(ZoneEntered {
+                zone: collision.other,
+                id: zone.id.clone(),
+                kind: zone.kind.clone(),
+            }),
+            CollisionKind::Stopped => exited.send(ZoneExited {
+                zone: collision.other,
+                id: zone.id.clone(),
+                kind: zone.kind.clone(),
+            }),
+        }
+    }
+}
+
+pub(super) fn react_to_zones(
+    mut entered: EventReader<ZoneEntered>,
+    zones: Query<&GlobalTransform, With<Zone>>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut respawn: EventWriter<RespawnPlayer>,
+    mut change_stage: EventWriter<ChangeStage>,
+) {
+    for event in entered.read() {
+        match &event.kind {
+            ZoneKind::Checkpoint => {
+                if let Ok(transform) = zones.get(event.zone) {
+                    checkpoint.0 = Some(transform.translation());
+                }
+            }
+            ZoneKind::KillPlane => respawn.send(RespawnPlayer),
+            ZoneKind::Exit { stage: Some(index) } => change_stage.send(ChangeStage::To(*index)),
+            ZoneKind::Exit { stage: None } => change_stage.send(ChangeStage::Next),
+            // the audio plugin plays the track while the player is inside
+            ZoneKind::Music { .. } => {}
+        }
+    }
+}
+
+// lets stage authors follow the player through the zones without the debug renderer
+pub(super) fn log_zone_events(
+    mut entered: EventReader<ZoneEntered>,
+    mut exited: EventReader<ZoneExited>,
+) {
+    for event in entered.read() {
+        debug!("Entered zone '{}' ({:?})", event.id, event.kind);
+    }
+    for event in exited.read() {
+        debug!("Left zone '{}' ({:?})", event.id, event.kind);
+    }
+}